@@ -0,0 +1,358 @@
+//! Benchmarks of the stages of the prediction on synthetic short reads, long
+//! reads and a complete genome. Run with `cargo bench`.
+
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use frag_gene_scan_rs::dna::{count_cg_content, Nuc};
+use frag_gene_scan_rs::gene::{GeneFilter, GeneIds, ReadPrediction};
+use frag_gene_scan_rs::hmm;
+use frag_gene_scan_rs::trellis::Trellis;
+use frag_gene_scan_rs::viterbi::{
+    backtrack, build_genes, forward, forward_scalar, lanes_supported, viterbi,
+};
+
+use strum::EnumCount;
+
+type Alpha = Vec<[f64; hmm::State::COUNT]>;
+type Path = Vec<[Option<hmm::State>; hmm::State::COUNT]>;
+
+/// xorshift, so the inputs are the same for every run
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    fn nucleotide(&mut self) -> u8 {
+        b"ACGT"[self.below(4) as usize]
+    }
+}
+
+/// A genome of alternating genes on both strands and intergenic regions.
+/// Genes are an ATG, random codons without stop codons, and a TAA.
+fn genome(random: &mut Random, length: usize) -> Vec<u8> {
+    let mut genome = vec![];
+    while genome.len() < length {
+        for _ in 0..50 + random.below(250) {
+            genome.push(random.nucleotide());
+        }
+        let mut gene = b"ATG".to_vec();
+        for _ in 0..100 + random.below(400) {
+            let codon = loop {
+                let codon = [
+                    random.nucleotide(),
+                    random.nucleotide(),
+                    random.nucleotide(),
+                ];
+                if !matches!(&codon, b"TAA" | b"TAG" | b"TGA") {
+                    break codon;
+                }
+            };
+            gene.extend_from_slice(&codon);
+        }
+        gene.extend_from_slice(b"TAA");
+        if random.below(2) == 0 {
+            gene.reverse();
+            for n in &mut gene {
+                *n = match n {
+                    b'A' => b'T',
+                    b'C' => b'G',
+                    b'G' => b'C',
+                    _ => b'A',
+                };
+            }
+        }
+        genome.append(&mut gene);
+    }
+    genome.truncate(length);
+    genome
+}
+
+/// Reads of `length` nucleotides from random positions of the genome, with
+/// each nucleotide substituted, deleted or followed by an insertion with
+/// probability `error` / 3.
+fn reads(
+    random: &mut Random,
+    genome: &[u8],
+    count: usize,
+    length: usize,
+    error: u64,
+) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|_| {
+            let start = random.below((genome.len() - length) as u64) as usize;
+            let mut read = vec![];
+            for &n in &genome[start..start + length] {
+                match random.below(3000) {
+                    e if e < error => read.push(random.nucleotide()),
+                    e if e < 2 * error => {}
+                    e if e < 3 * error => read.extend([n, random.nucleotide()]),
+                    _ => read.push(n),
+                }
+            }
+            read
+        })
+        .collect()
+}
+
+struct Workload {
+    name: &'static str,
+    train: &'static str,
+    whole_genome: bool,
+    seqs: Vec<Vec<Nuc>>,
+}
+
+fn workloads() -> Vec<Workload> {
+    let mut random = Random(0x2545_f491_4f6c_dd1d);
+    let genome = genome(&mut random, 200_000);
+    let nucleotides = |seqs: Vec<Vec<u8>>| -> Vec<Vec<Nuc>> {
+        seqs.into_iter()
+            .map(|seq| seq.into_iter().map(Nuc::from).collect())
+            .collect()
+    };
+    vec![
+        Workload {
+            name: "short_reads",
+            train: "illumina_5",
+            whole_genome: false,
+            seqs: nucleotides(reads(&mut random, &genome, 200, 150, 5)),
+        },
+        Workload {
+            name: "long_reads",
+            train: "454_30",
+            whole_genome: false,
+            seqs: nucleotides(reads(&mut random, &genome, 2, 10_000, 80)),
+        },
+        Workload {
+            name: "complete_genome",
+            train: "complete",
+            whole_genome: true,
+            seqs: nucleotides(vec![genome]),
+        },
+    ]
+}
+
+fn model(train: &str) -> (Box<hmm::Global>, Vec<hmm::Local>) {
+    hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from(train)).unwrap()
+}
+
+fn bench_model(c: &mut Criterion) {
+    let mut group = c.benchmark_group("model");
+    for train in ["illumina_5", "454_30", "complete"] {
+        group.bench_function(train, |b| b.iter(|| model(black_box(train))));
+    }
+    group.finish();
+}
+
+fn bench_stages(c: &mut Criterion) {
+    for workload in workloads() {
+        let (global, locals) = model(workload.train);
+        let local = |seq: &[Nuc]| &locals[count_cg_content(seq)];
+        let length: usize = workload.seqs.iter().map(Vec::len).sum();
+        let whole_genome = workload.whole_genome;
+        let min_length = GeneFilter::new(whole_genome).min_length;
+
+        let trellises: Vec<(Alpha, Path)> = workload
+            .seqs
+            .iter()
+            .map(|seq| forward(&global, local(seq), seq, &[], whole_genome).unwrap())
+            .collect();
+        let vpaths: Vec<Vec<hmm::State>> = trellises
+            .iter()
+            .map(|(alpha, path)| {
+                let mut vpath = vec![];
+                backtrack(alpha, path, &mut vpath);
+                vpath
+            })
+            .collect();
+        let predictions: Vec<ReadPrediction> = workload
+            .seqs
+            .iter()
+            .zip(&vpaths)
+            .zip(&trellises)
+            .enumerate()
+            .map(|(i, ((seq, vpath), (alpha, _)))| {
+                let head = format!("seq{}", i).into_bytes();
+                build_genes(
+                    local(seq),
+                    head,
+                    seq.clone(),
+                    whole_genome,
+                    min_length,
+                    vpath,
+                    alpha,
+                )
+            })
+            .collect();
+
+        let mut group = c.benchmark_group(workload.name);
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(length as u64));
+        group.bench_function("forward", |b| {
+            b.iter(|| {
+                for seq in &workload.seqs {
+                    black_box(
+                        forward::<Alpha, Path>(&global, local(seq), seq, &[], whole_genome)
+                            .unwrap(),
+                    );
+                }
+            })
+        });
+        group.bench_function("backtrack", |b| {
+            let mut vpath = vec![];
+            b.iter(|| {
+                for (alpha, path) in &trellises {
+                    black_box(backtrack(alpha, path, &mut vpath));
+                }
+            })
+        });
+        group.bench_function("build_genes", |b| {
+            b.iter_batched(
+                || workload.seqs.clone(),
+                |seqs| {
+                    for ((seq, vpath), (alpha, _)) in seqs.into_iter().zip(&vpaths).zip(&trellises)
+                    {
+                        let local = local(&seq);
+                        black_box(build_genes(
+                            local,
+                            vec![],
+                            seq,
+                            whole_genome,
+                            min_length,
+                            vpath,
+                            alpha,
+                        ));
+                    }
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("translation", |b| {
+            b.iter(|| {
+                for prediction in &predictions {
+                    for gene in &prediction.genes {
+                        black_box(gene.translate(whole_genome));
+                    }
+                }
+            })
+        });
+        let ids = GeneIds::default();
+        group.bench_function("output", |b| {
+            let mut buf = vec![];
+            b.iter(|| {
+                buf.clear();
+                for prediction in &predictions {
+                    prediction.meta(&mut buf, &ids).unwrap();
+                    prediction.gff(&mut buf, &ids).unwrap();
+                    prediction.dna(&mut buf, false, &ids).unwrap();
+                    prediction.protein(&mut buf, whole_genome, &ids).unwrap();
+                }
+                black_box(buf.len())
+            })
+        });
+        group.finish();
+    }
+}
+
+/// The whole decoder with each way of storing the trellis.
+fn bench_trellis(c: &mut Criterion) {
+    for workload in workloads() {
+        let (global, locals) = model(workload.train);
+        let length: usize = workload.seqs.iter().map(Vec::len).sum();
+        let filter = GeneFilter::new(workload.whole_genome);
+
+        let mut group = c.benchmark_group(format!("trellis/{}", workload.name));
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(length as u64));
+        for (name, trellis) in [("full", Trellis::Full), ("compact", Trellis::Compact)] {
+            group.bench_function(name, |b| {
+                b.iter_batched(
+                    || workload.seqs.clone(),
+                    |seqs| {
+                        for seq in seqs {
+                            black_box(
+                                viterbi(
+                                    &global,
+                                    &locals,
+                                    vec![],
+                                    seq,
+                                    &[],
+                                    workload.whole_genome,
+                                    &filter,
+                                    trellis,
+                                )
+                                .unwrap(),
+                            );
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+/// The forward pass with the match and insertion states in AVX2 lanes, if
+/// the processor supports them, and with the scalar kernel.
+fn bench_kernel(c: &mut Criterion) {
+    for workload in workloads() {
+        let (global, locals) = model(workload.train);
+        let local = |seq: &[Nuc]| &locals[count_cg_content(seq)];
+        let length: usize = workload.seqs.iter().map(Vec::len).sum();
+
+        let mut group = c.benchmark_group(format!("kernel/{}", workload.name));
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(length as u64));
+        if lanes_supported() {
+            group.bench_function("lanes", |b| {
+                b.iter(|| {
+                    for seq in &workload.seqs {
+                        black_box(
+                            forward::<Alpha, Path>(
+                                &global,
+                                local(seq),
+                                seq,
+                                &[],
+                                workload.whole_genome,
+                            )
+                            .unwrap(),
+                        );
+                    }
+                })
+            });
+        }
+        group.bench_function("scalar", |b| {
+            b.iter(|| {
+                for seq in &workload.seqs {
+                    black_box(
+                        forward_scalar::<Alpha, Path>(
+                            &global,
+                            local(seq),
+                            seq,
+                            &[],
+                            workload.whole_genome,
+                        )
+                        .unwrap(),
+                    );
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_model,
+    bench_stages,
+    bench_trellis,
+    bench_kernel
+);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+use frag_gene_scan_rs::gene::ReadPrediction;
+
+/// Checks that the genes lie within the sequence of `length` nucleotides and
+/// that they can be written out.
+pub fn check_prediction(prediction: &ReadPrediction, length: usize, whole_genome: bool) {
+    let mut buf = vec![];
+    let ids = Default::default();
+    prediction.meta(&mut buf, &ids).unwrap();
+    prediction.gff(&mut buf, &ids).unwrap();
+    prediction.dna(&mut buf, true, &ids).unwrap();
+    prediction.protein(&mut buf, whole_genome, &ids).unwrap();
+    for gene in &prediction.genes {
+        assert!(1 <= gene.start && gene.start <= gene.end && gene.end <= length);
+        assert!((1..=3).contains(&gene.frame));
+        for &position in gene.inserted.iter().chain(&gene.deleted) {
+            assert!(gene.start <= position && position <= gene.end);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Reads arbitrary bytes after the first as a FASTA file and predicts the
+//! genes on every record, as the binary does for short reads. The first
+//! byte is the minimum gene length, or 0 for the default.
+
+#![no_main]
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use seq_io::fasta;
+
+use frag_gene_scan_rs::dna::Nuc;
+use frag_gene_scan_rs::gene::GeneFilter;
+use frag_gene_scan_rs::hmm;
+use frag_gene_scan_rs::trellis::Trellis;
+use frag_gene_scan_rs::viterbi::{check, viterbi};
+
+mod common;
+
+fn model() -> &'static (Box<hmm::Global>, Vec<hmm::Local>) {
+    static MODEL: OnceLock<(Box<hmm::Global>, Vec<hmm::Local>)> = OnceLock::new();
+    MODEL.get_or_init(|| {
+        let train = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../train");
+        hmm::get_train_from_file(train, PathBuf::from("illumina_5")).unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (min_length, data) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let (global, locals) = model();
+    let mut filter = GeneFilter::new(false);
+    if *min_length > 0 {
+        filter.min_length = *min_length as usize;
+    }
+    for record in fasta::Reader::new(data).into_records() {
+        let fasta::OwnedRecord { head, seq } = match record {
+            Ok(record) => record,
+            Err(_) => return,
+        };
+        let nseq: Vec<Nuc> = seq.iter().map(|&n| Nuc::from(n)).collect();
+        let length = nseq.len();
+        match viterbi(
+            global,
+            locals,
+            head,
+            nseq.clone(),
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        ) {
+            Ok(prediction) => common::check_prediction(&prediction, length, false),
+            Err(e) => assert_eq!(Err(e), check(&nseq)),
+        }
+    }
+});
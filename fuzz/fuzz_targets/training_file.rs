@@ -0,0 +1,35 @@
+//! Parses arbitrary bytes as the transition training file, with the bundled
+//! emission files.
+
+#![no_main]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use libfuzzer_sys::fuzz_target;
+
+use frag_gene_scan_rs::hmm;
+
+const EMISSIONS: [&str; 8] = [
+    "gene",
+    "rgene",
+    "noncoding",
+    "start",
+    "stop",
+    "start1",
+    "stop1",
+    "pwm",
+];
+
+fuzz_target!(|data: &[u8]| {
+    let dir = std::env::temp_dir().join(format!("fgsrs-fuzz-{}", std::process::id()));
+    if !dir.exists() {
+        fs::create_dir_all(&dir).unwrap();
+        let train = Path::new(env!("CARGO_MANIFEST_DIR")).join("../train");
+        for name in EMISSIONS {
+            fs::copy(train.join(name), dir.join(name)).unwrap();
+        }
+    }
+    fs::write(dir.join("fuzz"), data).unwrap();
+    let _ = hmm::get_train_from_file(dir, PathBuf::from("fuzz"));
+});
@@ -0,0 +1,91 @@
+//! Predicts the genes on arbitrary bytes as a sequence, with every model,
+//! trellis, soft mask and minimum gene length, for short reads, complete
+//! genomic sequences and long reads.
+
+#![no_main]
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+
+use frag_gene_scan_rs::dna::Nuc;
+use frag_gene_scan_rs::gene::GeneFilter;
+use frag_gene_scan_rs::hmm;
+use frag_gene_scan_rs::trellis::Trellis;
+use frag_gene_scan_rs::viterbi::{check, viterbi, viterbi_long_read};
+
+mod common;
+
+const MODELS: [(&str, &str); 3] = [
+    ("train", "454_10"),
+    ("tests/data", "homopolymer"),
+    ("train", "complete"),
+];
+
+fn models() -> &'static Vec<(Box<hmm::Global>, Vec<hmm::Local>)> {
+    static LOADED: OnceLock<Vec<(Box<hmm::Global>, Vec<hmm::Local>)>> = OnceLock::new();
+    LOADED.get_or_init(|| {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..");
+        MODELS
+            .iter()
+            .map(|(dir, name)| {
+                hmm::get_train_from_file(root.join(dir), PathBuf::from(name)).unwrap()
+            })
+            .collect()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (options, min_length, data) = match data {
+        [options, min_length, data @ ..] => (*options, *min_length, data),
+        _ => return,
+    };
+    let (global, locals) = &models()[options as usize % MODELS.len()];
+    let whole_genome = options & 4 != 0;
+    let long_read = options & 32 != 0;
+    let trellis = if options & 8 != 0 {
+        Trellis::Compact
+    } else {
+        Trellis::Full
+    };
+    let mask: Vec<bool> = if options & 16 != 0 {
+        data.iter().map(u8::is_ascii_lowercase).collect()
+    } else {
+        vec![]
+    };
+    let seq: Vec<Nuc> = data.iter().map(|&n| Nuc::from(n)).collect();
+    // 0 keeps the default minimum gene length
+    let mut filter = GeneFilter::new(whole_genome || long_read);
+    if min_length > 0 {
+        filter.min_length = min_length as usize;
+    }
+    let result = if long_read {
+        viterbi_long_read(
+            global,
+            locals,
+            b"read".to_vec(),
+            seq.clone(),
+            &mask,
+            &filter,
+            trellis,
+        )
+    } else {
+        viterbi(
+            global,
+            locals,
+            b"read".to_vec(),
+            seq.clone(),
+            &mask,
+            whole_genome,
+            &filter,
+            trellis,
+        )
+    };
+    match result {
+        Ok(prediction) => {
+            common::check_prediction(&prediction, seq.len(), whole_genome || long_read)
+        }
+        Err(e) => assert_eq!(Err(e), check(&seq)),
+    }
+});
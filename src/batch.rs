@@ -0,0 +1,162 @@
+//! Grouping of reads into batches that are processed in parallel.
+
+use std::collections::VecDeque;
+
+/// How reads are grouped into batches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Batching {
+    /// Number of nucleotides after which a batch is closed. A read longer
+    /// than this forms a batch on its own.
+    pub bases: usize,
+    /// Number of batches read ahead. The largest of these is handed out
+    /// first, so a long sequence is not left to be processed on its own
+    /// at the end of the run.
+    pub lookahead: usize,
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Batching {
+            bases: 100_000,
+            lookahead: 1,
+        }
+    }
+}
+
+impl Batching {
+    /// Groups the items into batches, using `length` for the number of
+    /// nucleotides of an item. The batches are numbered in input order.
+    pub fn batches<I, F>(self, iterator: I, length: F) -> Batches<I, F>
+    where
+        I: Iterator,
+        F: Fn(&I::Item) -> usize,
+    {
+        Batches {
+            batching: self,
+            iterator,
+            length,
+            pending: VecDeque::new(),
+            held: None,
+            next: 0,
+            done: false,
+        }
+    }
+}
+
+pub struct Batches<I: Iterator, F> {
+    batching: Batching,
+    iterator: I,
+    length: F,
+    /// Batches read ahead, with their number and size, in input order.
+    pending: VecDeque<(usize, Vec<I::Item>, usize)>,
+    /// Read longer than a batch, held back to start the next batch.
+    held: Option<(I::Item, usize)>,
+    /// Number of the next batch to read.
+    next: usize,
+    done: bool,
+}
+
+impl<I, F> Batches<I, F>
+where
+    I: Iterator,
+    F: Fn(&I::Item) -> usize,
+{
+    fn read(&mut self) -> Option<(Vec<I::Item>, usize)> {
+        let mut items = vec![];
+        let mut bases = 0;
+        let limit = self.batching.bases.max(1);
+        while bases < limit {
+            let next = self.held.take().or_else(|| {
+                let item = self.iterator.next()?;
+                let length = (self.length)(&item);
+                Some((item, length))
+            });
+            match next {
+                Some((item, length)) if length > limit && !items.is_empty() => {
+                    self.held = Some((item, length));
+                    break;
+                }
+                Some((item, length)) => {
+                    bases += length;
+                    items.push(item);
+                }
+                None => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+        if items.is_empty() {
+            None
+        } else {
+            Some((items, bases))
+        }
+    }
+}
+
+impl<I, F> Iterator for Batches<I, F>
+where
+    I: Iterator,
+    F: Fn(&I::Item) -> usize,
+{
+    type Item = (usize, Vec<I::Item>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Only batches within the lookahead of the oldest pending batch are
+        // read, so that no batch is held back for more than that many.
+        let oldest = self
+            .pending
+            .front()
+            .map_or(self.next, |(index, _, _)| *index);
+        while !self.done && self.next < oldest + self.batching.lookahead.max(1) {
+            match self.read() {
+                Some((items, bases)) => {
+                    self.pending.push_back((self.next, items, bases));
+                    self.next += 1;
+                }
+                None => break,
+            }
+        }
+
+        let largest = (0..self.pending.len())
+            .rev()
+            .max_by_key(|&i| self.pending[i].2)?;
+        let (index, items, _) = self.pending.remove(largest).unwrap();
+        Some((index, items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches() {
+        let lengths = [40, 70, 10, 250, 30, 30, 30, 20];
+        let batching = Batching {
+            bases: 100,
+            lookahead: 1,
+        };
+        let batches: Vec<_> = batching.batches(lengths.iter(), |&&l| l).collect();
+        assert_eq!(
+            vec![
+                (0, vec![&40, &70]),
+                (1, vec![&10]),
+                (2, vec![&250]),
+                (3, vec![&30, &30, &30, &20]),
+            ],
+            batches
+        );
+
+        let lengths = [10, 10, 500, 10, 10, 10, 300, 10];
+        let batching = Batching {
+            bases: 20,
+            lookahead: 3,
+        };
+        let order: Vec<_> = batching
+            .batches(lengths.iter(), |&&l| l)
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(vec![1, 0, 4, 2, 3, 5], order);
+    }
+}
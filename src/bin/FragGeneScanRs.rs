@@ -1,68 +1,213 @@
 //! FragGeneScanRs executable
-#![allow(non_snake_case)]
+#![allow(non_snake_case, clippy::too_many_arguments)]
 
 use std::collections::VecDeque;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex};
 
 extern crate anyhow;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 extern crate clap;
-use clap::{crate_version, App, Arg};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+
+extern crate strum;
+use strum::VariantNames;
 
 extern crate seq_io;
-use seq_io::fasta;
+use seq_io::{fasta, fastq};
+
+extern crate serde_json;
+
+extern crate toml;
 
 extern crate rayon;
 use rayon::iter::{ParallelBridge, ParallelIterator};
-use rayon::ThreadPoolBuilder;
 
 extern crate frag_gene_scan_rs;
+use frag_gene_scan_rs::batch::Batching;
+use frag_gene_scan_rs::config::{self, Config};
+use frag_gene_scan_rs::convert;
+use frag_gene_scan_rs::diagnostics::{Diagnostic, Kind};
 use frag_gene_scan_rs::dna::Nuc;
+use frag_gene_scan_rs::evaluate;
 use frag_gene_scan_rs::gene;
 use frag_gene_scan_rs::hmm;
-use frag_gene_scan_rs::viterbi::viterbi;
+use frag_gene_scan_rs::pair;
+use frag_gene_scan_rs::peptide;
+use frag_gene_scan_rs::provenance::Provenance;
+use frag_gene_scan_rs::runner::Runner;
+use frag_gene_scan_rs::stats;
+use frag_gene_scan_rs::summary::Summary;
+use frag_gene_scan_rs::train;
+use frag_gene_scan_rs::trellis::Trellis;
+use frag_gene_scan_rs::viterbi;
+
+/// Subcommands, of which `predict` is run when none is given.
+const SUBCOMMANDS: [&str; 8] = [
+    "predict",
+    "train",
+    "validate-model",
+    "evaluate",
+    "stats",
+    "convert",
+    "completions",
+    "help",
+];
 
 fn main() -> Result<()> {
-    let matches = App::new("FragGeneScanRs")
+    let matches = app().get_matches_from(with_default_subcommand(env::args_os().collect()));
+    match matches.subcommand() {
+        ("train", Some(matches)) => train(matches),
+        ("validate-model", Some(matches)) => validate_model(matches),
+        ("evaluate", Some(matches)) => evaluate(matches),
+        ("stats", Some(matches)) => stats(matches),
+        ("convert", Some(matches)) => convert(matches),
+        ("completions", Some(matches)) => {
+            let shell = matches.value_of("shell").unwrap().parse::<Shell>().unwrap();
+            app().gen_completions_to("FragGeneScanRs", shell, &mut io::stdout());
+            Ok(())
+        }
+        (_, matches) => predict(matches.unwrap()),
+    }
+}
+
+/// Inserts the `predict` subcommand if the arguments don't start with one,
+/// so command lines of earlier versions keep working.
+fn with_default_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let first = args.get(1).and_then(|arg| arg.to_str());
+    let explicit = match first {
+        Some(arg) => {
+            SUBCOMMANDS.contains(&arg) || ["-h", "--help", "-V", "--version"].contains(&arg)
+        }
+        None => false,
+    };
+    if !explicit {
+        args.insert(1.min(args.len()), OsString::from("predict"));
+    }
+    args
+}
+
+/// Validates that an argument parses as a `T`, so that invalid values are
+/// reported with the usage.
+fn parses<T: FromStr>(value: String) -> std::result::Result<(), String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse::<T>()
+        .map(|_| ())
+        .map_err(|e| format!("'{}': {}", value, e))
+}
+
+fn probability(value: String) -> std::result::Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(p) if p > 0.0 && p <= 1.0 => Ok(()),
+        _ => Err(format!("'{}' is not a probability greater than 0", value)),
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("FragGeneScanRs")
         .version(crate_version!())
         .author("Felix Van der Jeugt <felix.vanderjeugt@ugent.be>")
         .about("Scalable high-throughput short-read open reading frame prediction.")
-        .arg(Arg::with_name("seq-file")
-            .short("s")
-            .long("seq-file-name")
-            .value_name("seq_file_name")
-            .takes_value(true)
-            .default_value("stdin")
-            .help("Sequence file name including the full path. Using 'stdin' (or not suplying this argument) reads from standard input."))
-        .arg(Arg::with_name("output-prefix")
-            .short("o")
-            .long("output-prefix")
-            .value_name("output_prefix")
-            .takes_value(true)
-            .help("Output metadata (.out and .gff), proteins (.faa) and genes (.ffn) to files with this prefix. Don't pass this argument or use 'stdout' to write the predicted proteins to standard output."))
-        .arg(Arg::with_name("complete")
-            .short("w")
-            .long("complete")
-            .value_name("complete")
-            .takes_value(true)
-            .default_value("0")
-            .help("The input sequence has complete genomic sequences; not short sequence reads."))
-        .arg(Arg::with_name("formatted")
-            .short("f")
-            .long("formatted")
-            .help("Format the DNA output."))
-        .arg(Arg::with_name("train-file")
-            .short("t")
-            .long("training-file")
-            .value_name("train_file_name")
-            .takes_value(true)
-            .required(true)
-            .help("File name that contains model parameters; this file should be in the -r directory or one of the following:
+        .setting(AppSettings::VersionlessSubcommands)
+        .after_help("Without a subcommand, the arguments are those of predict.")
+        .subcommand(SubCommand::with_name("predict")
+            .about("Predict genes in sequence reads or genomes (the default).")
+            .arg(Arg::with_name("config")
+                .long("config")
+                .value_name("config")
+                .takes_value(true)
+                .help("Read the options from this TOML file, with a section for each group of options (model, input, output, filter, digestion and threading) and their long names as keys. Options given on the command line take precedence."))
+            .arg(Arg::with_name("preset")
+                .long("preset")
+                .value_name("preset")
+                .takes_value(true)
+                .possible_values(&config::PRESETS.map(|(name, _)| name))
+                .help("Start from the options for short reads (illumina_5) or complete genomic sequences (complete with -w). The configuration file and the command line take precedence."))
+            .arg(Arg::with_name("print-config")
+                .long("print-config")
+                .help("Print the effective configuration in TOML, with the version of FragGeneScanRs and the checksum of the model, instead of predicting genes. Pass the output to --config to repeat the run."))
+            .arg(Arg::with_name("seq-file")
+                .short("s")
+                .long("seq-file-name")
+                .value_name("seq_file_name")
+                .takes_value(true)
+                .default_value("stdin")
+                .help("Sequence file name including the full path. Using 'stdin' (or not suplying this argument) reads from standard input. Input starting with '@' is read as fastq."))
+            .arg(Arg::with_name("mate-file")
+                .long("mate-file")
+                .value_name("mate_file")
+                .takes_value(true)
+                .conflicts_with("interleaved")
+                .help("Sequence file with the second mates of the paired-end reads in the -s file, in the same order."))
+            .arg(Arg::with_name("interleaved")
+                .long("interleaved")
+                .help("The -s file contains paired-end reads, with both mates of each pair in consecutive records."))
+            .arg(Arg::with_name("merge-mates")
+                .long("merge-mates")
+                .help("Merge overlapping mates of paired-end reads into a single fragment before prediction, using quality scores for fastq input. Predictions are reported on both mates."))
+            .arg(Arg::with_name("reconcile")
+                .long("reconcile")
+                .help("For paired-end reads with overlapping mates, also predict genes on the fragment joining both mates."))
+            .arg(Arg::with_name("pair-file")
+                .long("pair-file")
+                .value_name("pair_file")
+                .takes_value(true)
+                .help("Output, for each pair of mates, whether their predictions agree on strand and frame to this file. Use - to write to standard output (use only once)."))
+            .arg(Arg::with_name("output-prefix")
+                .short("o")
+                .long("output-prefix")
+                .value_name("output_prefix")
+                .takes_value(true)
+                .help("Output metadata (.out and .gff), proteins (.faa) and genes (.ffn) to files with this prefix. Don't pass this argument or use 'stdout' to write the predicted proteins to standard output."))
+            .arg(Arg::with_name("complete")
+                .short("w")
+                .long("complete")
+                .value_name("complete")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .possible_values(&["0", "1"])
+                .help("The input sequence has complete genomic sequences; not short sequence reads. The values 0 and 1 of earlier versions are still accepted."))
+            .arg(Arg::with_name("long-reads")
+                .long("long-reads")
+                .help("The input sequences are long reads (Nanopore or PacBio CLR) with sequencing errors. Corrects insertions and deletions while refining start codons as for complete genomic sequences. None of the bundled training files is trained on long reads."))
+            .arg(Arg::with_name("soft-mask")
+                .long("soft-mask")
+                .value_name("soft_mask")
+                .takes_value(true)
+                .possible_values(&["ignore", "noncoding"])
+                .default_value("ignore")
+                .help("How to handle lowercase (soft-masked) nucleotides in the input: 'ignore' treats them as uppercase, 'noncoding' does not allow genes to overlap them."))
+            .arg(Arg::with_name("formatted")
+                .short("f")
+                .long("formatted")
+                .help("Format the DNA output."))
+            .arg(Arg::with_name("full-headers")
+                .long("full-headers")
+                .help("Keep the full FASTA/FASTQ header of each read, and write the part after the identifier after the gene identifiers in the protein and DNA output."))
+            .arg(Arg::with_name("gene-id")
+                .long("gene-id")
+                .value_name("gene_id")
+                .takes_value(true)
+                .default_value(gene::GeneIds::DEFAULT)
+                .help("Template for the identifiers of predicted genes in the protein, DNA, GFF and meta output. The names read, n (number of the gene in the read, from 1), start, end, strand and frame in braces are replaced by their values, e.g. '{read}|{start}-{end}|{strand}|frame={frame}'."))
+            .arg(Arg::with_name("train-file")
+                .short("t")
+                .long("training-file")
+                .value_name("train_file_name")
+                .takes_value(true)
+                .help("File name that contains model parameters; this file should be in the -r directory (./train by default) or one of the following:
 [complete] for complete genomic sequences or short sequence reads without sequencing error
 [sanger_5] for Sanger sequencing reads with about 0.5% error rate
 [sanger_10] for Sanger sequencing reads with about 1% error rate
@@ -72,99 +217,639 @@ fn main() -> Result<()> {
 [illumina_1] for Illumina sequencing reads with about 0.1% error rate
 [illumina_5] for Illumina sequencing reads with about 0.5% error rate
 [illumina_10] for Illumina sequencing reads with about 1% error rate"))
-        .arg(Arg::with_name("train-file-dir")
-            .short("r")
-            .long("train-file-dir")
-            .value_name("train_file_dir")
-            .takes_value(true)
-            .help("Full path of the directory containing the training model files."))
-        .arg(Arg::with_name("thread-num")
-            .short("p")
-            .long("thread-num")
-            .value_name("thread_num")
-            .takes_value(true)
-            .default_value("1")
-            .help("The number of threads used by FragGeneScan++."))
-        .arg(Arg::with_name("meta-file")
-            .short("m")
-            .long("meta-file")
-            .value_name("meta_file")
-            .takes_value(true)
-            .help("Output metadata to this file (supersedes -o). Use - to write to standard output (use only once)."))
-        .arg(Arg::with_name("gff-file")
-            .short("g")
-            .long("gff-file")
-            .value_name("gff_file")
-            .takes_value(true)
-            .help("Output metadata to this gff formatted file (supersedes -o). Use - to write to standard output (use only once)."))
-        .arg(Arg::with_name("aa-file")
-            .short("a")
-            .long("aa-file")
-            .value_name("aa_file")
-            .takes_value(true)
-            .help("Output predicted proteins to this file (supersedes -o). Use - to write to standard output (use only once)."))
-        .arg(Arg::with_name("nucleotide-file")
-            .short("n")
-            .long("nucleotide-file")
-            .value_name("nucleotide_file")
-            .takes_value(true)
-            .help("Output predicted genes to this file (supersedes -o). Use - to write to standard output (use only once)."))
-        .arg(Arg::with_name("unordered")
-            .short("u")
-            .long("unordered")
-            .help("Do not preserve record order in output (faster)."))
-        .get_matches();
-
-    let (global, locals) = hmm::get_train_from_file(
-        PathBuf::from(matches.value_of("train-file-dir").unwrap_or("train")),
-        PathBuf::from(matches.value_of("train-file").unwrap()),
+            .arg(Arg::with_name("train-file-dir")
+                .short("r")
+                .long("train-file-dir")
+                .value_name("train_file_dir")
+                .takes_value(true)
+                .help("Full path of the directory containing the training model files."))
+            .arg(Arg::with_name("thread-num")
+                .short("p")
+                .long("thread-num")
+                .value_name("thread_num")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .default_value("1")
+                .help("The number of threads used by FragGeneScan++."))
+            .arg(Arg::with_name("meta-file")
+                .short("m")
+                .long("meta-file")
+                .value_name("meta_file")
+                .takes_value(true)
+                .help("Output metadata to this file (supersedes -o). Use - to write to standard output (use only once)."))
+            .arg(Arg::with_name("gff-file")
+                .short("g")
+                .long("gff-file")
+                .value_name("gff_file")
+                .takes_value(true)
+                .help("Output metadata to this gff formatted file (supersedes -o). Use - to write to standard output (use only once)."))
+            .arg(Arg::with_name("aa-file")
+                .short("a")
+                .long("aa-file")
+                .value_name("aa_file")
+                .takes_value(true)
+                .help("Output predicted proteins to this file (supersedes -o). Use - to write to standard output (use only once)."))
+            .arg(Arg::with_name("nucleotide-file")
+                .short("n")
+                .long("nucleotide-file")
+                .value_name("nucleotide_file")
+                .takes_value(true)
+                .help("Output predicted genes to this file (supersedes -o). Use - to write to standard output (use only once)."))
+            .arg(Arg::with_name("unordered")
+                .short("u")
+                .long("unordered")
+                .help("Do not preserve record order in output (faster)."))
+            .arg(Arg::with_name("reorder-window")
+                .long("reorder-window")
+                .value_name("reorder_window")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .conflicts_with("unordered")
+                .help("Maximum number of batches that are read before the output of all earlier batches is written, which bounds the memory used to preserve the record order. Defaults to 4 times the number of threads."))
+            .arg(Arg::with_name("batch-size")
+                .long("batch-size")
+                .value_name("batch_size")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .help("Number of nucleotides after which a batch of reads is handed to a thread. Longer sequences form a batch on their own, and the largest batches within the reorder window are processed first. Defaults to 100000."))
+            .arg(Arg::with_name("compact-trellis")
+                .long("compact-trellis")
+                .help("Store the scores of the decoder in single precision and its traceback in 5 bits per state, which about halves the memory used for long sequences. Gene scores may differ in the last digits."))
+            .arg(Arg::with_name("min-gene-length")
+                .long("min-gene-length")
+                .value_name("min_gene_length")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .help("Minimum length (in nucleotides) of a predicted gene. Defaults to 61, or 121 for complete genomic sequences."))
+            .arg(Arg::with_name("min-protein-length")
+                .long("min-protein-length")
+                .value_name("min_protein_length")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .default_value("0")
+                .help("Minimum length (in amino acids) of a predicted protein."))
+            .arg(Arg::with_name("min-score")
+                .long("min-score")
+                .value_name("min_score")
+                .takes_value(true)
+                .validator(parses::<f64>)
+                .allow_hyphen_values(true)
+                .help("Minimum score of a predicted gene."))
+            .arg(Arg::with_name("max-indels")
+                .long("max-indels")
+                .value_name("max_indels")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .help("Maximum number of inserted and deleted nucleotides in a predicted gene."))
+            .arg(Arg::with_name("match-insertion")
+                .long("match-insertion")
+                .value_name("probability")
+                .takes_value(true)
+                .validator(probability)
+                .help("Override the match to insertion transition probability (MI) of the training file."))
+            .arg(Arg::with_name("match-deletion")
+                .long("match-deletion")
+                .value_name("probability")
+                .takes_value(true)
+                .validator(probability)
+                .help("Override the match to deletion transition probability (MD) of the training file."))
+            .arg(Arg::with_name("insertion-insertion")
+                .long("insertion-insertion")
+                .value_name("probability")
+                .takes_value(true)
+                .validator(probability)
+                .help("Override the insertion to insertion transition probability (II) of the training file."))
+            .arg(Arg::with_name("deletion-deletion")
+                .long("deletion-deletion")
+                .value_name("probability")
+                .takes_value(true)
+                .validator(probability)
+                .help("Override the deletion to deletion transition probability (DD) of the training file."))
+            .arg(Arg::with_name("deleted-nucleotide")
+                .long("deleted-nucleotide")
+                .value_name("probability")
+                .takes_value(true)
+                .validator(probability)
+                .help("Override the probability of each nucleotide skipped by a deletion, 0.25 by default. Lower values make longer deletions less likely."))
+            .arg(Arg::with_name("max-deletion-length")
+                .long("max-deletion-length")
+                .value_name("max_deletion_length")
+                .takes_value(true)
+                .possible_values(&["0", "1", "2", "3", "4", "5"])
+                .default_value("5")
+                .help("Longest run of consecutive deleted nucleotides considered by the model. Has no effect on complete genomic sequences."))
+            .arg(Arg::with_name("peptide-file")
+                .long("peptide-file")
+                .value_name("peptide_file")
+                .takes_value(true)
+                .help("Output peptides from the in silico digestion of the predicted proteins to this file. Use - to write to standard output (use only once)."))
+            .arg(Arg::with_name("enzyme")
+                .long("enzyme")
+                .value_name("enzyme")
+                .takes_value(true)
+                .possible_values(peptide::Enzyme::VARIANTS)
+                .default_value("trypsin")
+                .help("Enzyme used for the in silico digestion."))
+            .arg(Arg::with_name("missed-cleavages")
+                .long("missed-cleavages")
+                .value_name("missed_cleavages")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .default_value("0")
+                .help("Maximum number of missed cleavages in a digested peptide."))
+            .arg(Arg::with_name("min-peptide-length")
+                .long("min-peptide-length")
+                .value_name("min_peptide_length")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .default_value("5")
+                .help("Minimum length of a digested peptide."))
+            .arg(Arg::with_name("max-peptide-length")
+                .long("max-peptide-length")
+                .value_name("max_peptide_length")
+                .takes_value(true)
+                .validator(parses::<usize>)
+                .default_value("50")
+                .help("Maximum length of a digested peptide."))
+            .arg(Arg::with_name("summary-file")
+                .long("summary-file")
+                .value_name("summary_file")
+                .takes_value(true)
+                .help("Output summary statistics of the run to this JSON file. A human-readable summary is always written to standard error."))
+            .arg(Arg::with_name("diagnostics-file")
+                .long("diagnostics-file")
+                .value_name("diagnostics_file")
+                .takes_value(true)
+                .help("Output problems with individual reads (malformed records, invalid headers, None-states in the traceback and skipped sequences) as tab-separated lines with the read, the kind of problem and a message to this file. Use - to write to standard output (use only once). By default, they are written to standard error as warnings."))
+            .arg(Arg::with_name("strict")
+                .long("strict")
+                .help("Abort the run on the first problem with an individual read, instead of reporting it and continuing.")))
+        .subcommand(SubCommand::with_name("train")
+            .about("Estimate the transitions between genes and noncoding regions of a training file from reference annotations of complete genomic sequences. The other transitions and the emissions are copied from the base training file.")
+            .arg(Arg::with_name("annotations")
+                .short("g")
+                .long("annotations")
+                .value_name("annotations")
+                .takes_value(true)
+                .required(true)
+                .help("Reference annotations of the genomic sequences in gff format."))
+            .arg(Arg::with_name("feature")
+                .short("f")
+                .long("feature")
+                .value_name("feature")
+                .takes_value(true)
+                .default_value("CDS")
+                .help("Feature type of the reference genes in the gff file."))
+            .arg(Arg::with_name("seq-file")
+                .short("s")
+                .long("seq-file-name")
+                .value_name("seq_file_name")
+                .takes_value(true)
+                .help("Sequence file of the annotations, to determine the sequence lengths. Defaults to ##sequence-region pragmas in the reference annotations."))
+            .arg(Arg::with_name("train-file")
+                .short("t")
+                .long("training-file")
+                .value_name("train_file_name")
+                .takes_value(true)
+                .default_value("complete")
+                .help("Base training file, in the -r directory (./train by default) or one of the bundled training files."))
+            .arg(Arg::with_name("train-file-dir")
+                .short("r")
+                .long("train-file-dir")
+                .value_name("train_file_dir")
+                .takes_value(true)
+                .help("Full path of the directory containing the base training file."))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("output")
+                .takes_value(true)
+                .help("Write the training file to this file instead of standard output. Put it in a directory with the emission files (gene, rgene, noncoding, start, stop, start1, stop1 and pwm) to use it with -r, or leave them out to use the bundled ones.")))
+        .subcommand(SubCommand::with_name("validate-model")
+            .about("Check that a training file parses and that its values are probabilities.")
+            .arg(Arg::with_name("train-file")
+                .short("t")
+                .long("training-file")
+                .value_name("train_file_name")
+                .takes_value(true)
+                .required(true)
+                .help("Training file, in the -r directory (./train by default) or one of the bundled training files."))
+            .arg(Arg::with_name("train-file-dir")
+                .short("r")
+                .long("train-file-dir")
+                .value_name("train_file_dir")
+                .takes_value(true)
+                .help("Full path of the directory containing the training model files.")))
+        .subcommand(SubCommand::with_name("evaluate")
+            .about("Compare predictions to reference annotations.")
+            .arg(Arg::with_name("predictions")
+                .short("p")
+                .long("predictions")
+                .value_name("predictions")
+                .takes_value(true)
+                .required(true)
+                .help("Predictions of FragGeneScanRs, in metadata (.out) or gff format. Files whose first line that is not a '#' comment is a '>' header are read as metadata, others as gff."))
+            .arg(Arg::with_name("annotations")
+                .short("g")
+                .long("annotations")
+                .value_name("annotations")
+                .takes_value(true)
+                .required_unless("mapping")
+                .conflicts_with("mapping")
+                .help("Reference annotations of the genomic sequences in gff format."))
+            .arg(Arg::with_name("feature")
+                .short("f")
+                .long("feature")
+                .value_name("feature")
+                .takes_value(true)
+                .default_value("CDS")
+                .help("Feature type of the reference genes in the gff file."))
+            .arg(Arg::with_name("mapping")
+                .short("m")
+                .long("mapping")
+                .value_name("mapping")
+                .takes_value(true)
+                .help("Tab-separated file with the reference genes in read coordinates: the read identifier, start, end and strand."))
+            .arg(Arg::with_name("seq-file")
+                .short("s")
+                .long("seq-file-name")
+                .value_name("seq_file_name")
+                .takes_value(true)
+                .help("Sequence file used for the predictions, to determine the sequence lengths. Defaults to ##sequence-region pragmas in the reference annotations."))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Output the evaluation in JSON format.")))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Report the number, lengths and GC content of input sequences, and the GC content models they would be predicted with.")
+            .arg(Arg::with_name("seq-file")
+                .short("s")
+                .long("seq-file-name")
+                .value_name("seq_file_name")
+                .takes_value(true)
+                .default_value("stdin")
+                .help("Sequence file name including the full path. Using 'stdin' (or not suplying this argument) reads from standard input. Input starting with '@' is read as fastq."))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Output the statistics in JSON format.")))
+        .subcommand(SubCommand::with_name("convert")
+            .about("Convert predictions in metadata (.out) format to other formats.")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .value_name("input")
+                .takes_value(true)
+                .default_value("stdin")
+                .help("Metadata file. Using 'stdin' (or not suplying this argument) reads from standard input."))
+            .arg(Arg::with_name("format")
+                .long("to")
+                .value_name("format")
+                .takes_value(true)
+                .possible_values(convert::Format::VARIANTS)
+                .default_value("gff")
+                .help("Output format: 'gff' as written with -g, or 'bed' with 0-based start positions."))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("output")
+                .takes_value(true)
+                .help("Write to this file instead of standard output."))
+            .arg(Arg::with_name("gene-id")
+                .long("gene-id")
+                .value_name("gene_id")
+                .takes_value(true)
+                .default_value(gene::GeneIds::DEFAULT)
+                .help("Template for the identifiers of the genes, as in predict. Identifiers in the metadata (written with a custom --gene-id) take precedence.")))
+        .subcommand(SubCommand::with_name("completions")
+            .about("Generate a shell completion script.")
+            .arg(Arg::with_name("shell")
+                .required(true)
+                .possible_values(&Shell::variants())
+                .help("Shell to generate the completion script for.")))
+}
+
+/// Directory of the training files when none is given with -r.
+const TRAIN_DIR: &str = "train";
+
+/// The directory and training file. Without a directory, the training file
+/// is read from `./train` if it is there, and has to be one of the bundled
+/// training files otherwise.
+fn training_file(name: &str, dir: Option<&str>) -> Result<(PathBuf, PathBuf)> {
+    training_file_in(Path::new(TRAIN_DIR), name, dir)
+}
+
+/// As `training_file`, with `default_dir` instead of `./train`.
+fn training_file_in(
+    default_dir: &Path,
+    name: &str,
+    dir: Option<&str>,
+) -> Result<(PathBuf, PathBuf)> {
+    match dir {
+        Some(dir) => Ok((PathBuf::from(dir), PathBuf::from(name))),
+        None => {
+            if !default_dir.join(name).is_file() {
+                name.parse::<hmm::Model>().map_err(|_| {
+                    anyhow!(
+                        "unknown training file '{}': use one of {}, a file in {}, or pass its directory with -r",
+                        name,
+                        hmm::Model::VARIANTS.join(", "),
+                        default_dir.display()
+                    )
+                })?;
+            }
+            Ok((default_dir.to_path_buf(), PathBuf::from(name)))
+        }
+    }
+}
+
+/// Type of the value of an option in the configuration.
+enum ValueType {
+    Flag,
+    Text,
+    Integer,
+    Float,
+}
+
+/// The options of predict that can be set in the configuration, with
+/// their section and key.
+const OPTIONS: [(&str, &str, &str, ValueType); 42] = [
+    ("train-file", "model", "training-file", ValueType::Text),
+    ("train-file-dir", "model", "train-file-dir", ValueType::Text),
+    ("complete", "model", "complete", ValueType::Flag),
+    ("long-reads", "model", "long-reads", ValueType::Flag),
+    (
+        "match-insertion",
+        "model",
+        "match-insertion",
+        ValueType::Float,
+    ),
+    (
+        "match-deletion",
+        "model",
+        "match-deletion",
+        ValueType::Float,
+    ),
+    (
+        "insertion-insertion",
+        "model",
+        "insertion-insertion",
+        ValueType::Float,
+    ),
+    (
+        "deletion-deletion",
+        "model",
+        "deletion-deletion",
+        ValueType::Float,
+    ),
+    (
+        "deleted-nucleotide",
+        "model",
+        "deleted-nucleotide",
+        ValueType::Float,
+    ),
+    (
+        "max-deletion-length",
+        "model",
+        "max-deletion-length",
+        ValueType::Integer,
+    ),
+    (
+        "compact-trellis",
+        "model",
+        "compact-trellis",
+        ValueType::Flag,
+    ),
+    ("seq-file", "input", "seq-file-name", ValueType::Text),
+    ("mate-file", "input", "mate-file", ValueType::Text),
+    ("interleaved", "input", "interleaved", ValueType::Flag),
+    ("merge-mates", "input", "merge-mates", ValueType::Flag),
+    ("reconcile", "input", "reconcile", ValueType::Flag),
+    ("soft-mask", "input", "soft-mask", ValueType::Text),
+    ("full-headers", "input", "full-headers", ValueType::Flag),
+    ("strict", "input", "strict", ValueType::Flag),
+    ("output-prefix", "output", "output-prefix", ValueType::Text),
+    ("meta-file", "output", "meta-file", ValueType::Text),
+    ("gff-file", "output", "gff-file", ValueType::Text),
+    ("aa-file", "output", "aa-file", ValueType::Text),
+    (
+        "nucleotide-file",
+        "output",
+        "nucleotide-file",
+        ValueType::Text,
+    ),
+    ("peptide-file", "output", "peptide-file", ValueType::Text),
+    ("pair-file", "output", "pair-file", ValueType::Text),
+    ("summary-file", "output", "summary-file", ValueType::Text),
+    (
+        "diagnostics-file",
+        "output",
+        "diagnostics-file",
+        ValueType::Text,
+    ),
+    ("formatted", "output", "formatted", ValueType::Flag),
+    ("gene-id", "output", "gene-id", ValueType::Text),
+    (
+        "min-gene-length",
+        "filter",
+        "min-gene-length",
+        ValueType::Integer,
+    ),
+    (
+        "min-protein-length",
+        "filter",
+        "min-protein-length",
+        ValueType::Integer,
+    ),
+    ("min-score", "filter", "min-score", ValueType::Float),
+    ("max-indels", "filter", "max-indels", ValueType::Integer),
+    ("enzyme", "digestion", "enzyme", ValueType::Text),
+    (
+        "missed-cleavages",
+        "digestion",
+        "missed-cleavages",
+        ValueType::Integer,
+    ),
+    (
+        "min-peptide-length",
+        "digestion",
+        "min-peptide-length",
+        ValueType::Integer,
+    ),
+    (
+        "max-peptide-length",
+        "digestion",
+        "max-peptide-length",
+        ValueType::Integer,
+    ),
+    ("thread-num", "threading", "thread-num", ValueType::Integer),
+    ("unordered", "threading", "unordered", ValueType::Flag),
+    (
+        "reorder-window",
+        "threading",
+        "reorder-window",
+        ValueType::Integer,
+    ),
+    ("batch-size", "threading", "batch-size", ValueType::Integer),
+];
+
+/// The configuration of the preset, overridden by the configuration file
+/// and then by the options given on the command line.
+fn configuration(matches: &ArgMatches) -> Result<Config> {
+    let mut table = match matches.value_of("preset") {
+        Some(name) => config::preset(name)?,
+        None => toml::Table::new(),
+    };
+    if let Some(filename) = matches.value_of("config") {
+        let content = std::fs::read_to_string(filename)?;
+        config::merge(&mut table, config::parse(&content)?);
+    }
+
+    let mut options = toml::Table::new();
+    for (arg, section, key, kind) in &OPTIONS {
+        if matches.occurrences_of(arg) == 0 {
+            continue;
+        }
+        let value = matches.value_of(arg);
+        let value = match kind {
+            // a flag given as `-w 0`
+            ValueType::Flag => toml::Value::Boolean(value != Some("0")),
+            ValueType::Text => toml::Value::String(value.unwrap().to_owned()),
+            ValueType::Integer => toml::Value::Integer(value.unwrap().parse()?),
+            ValueType::Float => toml::Value::Float(value.unwrap().parse()?),
+        };
+        let mut option = toml::Table::new();
+        option.insert(key.to_string(), value);
+        let mut layer = toml::Table::new();
+        layer.insert(section.to_string(), toml::Value::Table(option));
+        config::merge(&mut options, layer);
+    }
+    config::merge(&mut table, options);
+
+    Ok(Config::from_table(table)?)
+}
+
+fn predict(matches: &ArgMatches) -> Result<()> {
+    let mut config = configuration(matches)?;
+    let (model, input, output, threading) = (
+        &config.model,
+        &config.input,
+        &config.output,
+        &config.threading,
+    );
+    let (train_dir, train_file) = training_file(
+        model.training_file.as_deref().ok_or_else(|| {
+            anyhow!("no training file: pass -t or --preset, or set training-file in the [model] section of the configuration")
+        })?,
+        model.train_file_dir.as_deref(),
     )?;
+    if let Some(expected) = &config.model_checksum {
+        let checksum = hmm::checksum(train_dir.clone(), train_file.clone())?;
+        if *expected != checksum {
+            return Err(anyhow!(
+                "the training file has checksum {}, but the configuration expects {}",
+                checksum,
+                expected
+            ));
+        }
+    }
+    if let Some(version) = &config.version {
+        if version != crate_version!() {
+            eprintln!(
+                "Warning: the configuration was written by FragGeneScanRs {}",
+                version
+            );
+        }
+    }
+    if matches.is_present("print-config") {
+        config.version = Some(crate_version!().to_owned());
+        config.model_checksum = Some(hmm::checksum(train_dir, train_file)?);
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
+
+    let provenance = Provenance::new(
+        model.training_file.as_deref().unwrap_or_default(),
+        &hmm::checksum(train_dir.clone(), train_file.clone())?,
+        model.complete,
+    );
+    let (mut global, locals) = hmm::get_train_from_file(train_dir, train_file)?;
+    global.apply_overrides(model)?;
 
-    let inputseqs: Box<dyn Read + Send> = match matches.value_of("seq-file").unwrap_or("stdin") {
+    let inputseqs: Box<dyn Read + Send> = match input.seq_file_name.as_str() {
         "stdin" => Box::new(io::stdin()),
         filename => Box::new(File::open(filename)?),
     };
-
-    let mut aastream: Option<Box<dyn Write + Send>> = match (
-        matches.value_of("aa-file"),
-        matches.value_of("output-prefix"),
-    ) {
-        (Some("-"), _) => Some(Box::new(io::stdout())),
-        (Some(filename), _) => Some(Box::new(File::create(filename)?)),
-        (None, Some("stdout")) => Some(Box::new(io::stdout())),
-        (None, Some(filename)) => Some(Box::new(File::create(filename.to_owned() + ".faa")?)),
-        (None, None) => None,
+    let mateseqs: Option<Box<dyn Read + Send>> = match input.mate_file.as_deref() {
+        Some("stdin") => Some(Box::new(io::stdin())),
+        Some(filename) => Some(Box::new(File::open(filename)?)),
+        None => None,
     };
+    let interleaved = input.interleaved;
+    if mateseqs.is_some() && interleaved {
+        return Err(anyhow!(
+            "--mate-file and --interleaved cannot be used together"
+        ));
+    }
+    if (mateseqs.is_none() && !interleaved)
+        && (input.merge_mates || input.reconcile || output.pair_file.is_some())
+    {
+        return Err(anyhow!(
+            "--merge-mates, --reconcile and --pair-file require paired-end reads (--mate-file or --interleaved)"
+        ));
+    }
 
-    let metastream: Option<Box<dyn Write + Send>> = match (
-        matches.value_of("meta-file"),
-        matches.value_of("output-prefix"),
-    ) {
-        (Some("-"), _) => Some(Box::new(io::stdout())),
-        (Some(filename), _) => Some(Box::new(File::create(filename)?)),
-        (None, Some("stdout")) => None,
-        (None, Some(filename)) => Some(Box::new(File::create(filename.to_owned() + ".out")?)),
-        (None, None) => None,
-    };
+    let mut aastream: Option<Box<dyn Write + Send>> =
+        match (output.aa_file.as_deref(), output.output_prefix.as_deref()) {
+            (Some("-"), _) => Some(Box::new(io::stdout())),
+            (Some(filename), _) => Some(Box::new(File::create(filename)?)),
+            (None, Some("stdout")) => Some(Box::new(io::stdout())),
+            (None, Some(filename)) => Some(Box::new(File::create(filename.to_owned() + ".faa")?)),
+            (None, None) => None,
+        };
 
-    let mut gffstream: Option<Box<dyn Write + Send>> = match (
-        matches.value_of("gff-file"),
-        matches.value_of("output-prefix"),
-    ) {
-        (Some("-"), _) => Some(Box::new(io::stdout())),
-        (Some(filename), _) => Some(Box::new(File::create(filename)?)),
-        (None, Some("stdout")) => None,
-        (None, Some(filename)) => Some(Box::new(File::create(filename.to_owned() + ".gff")?)),
-        (None, None) => None,
-    };
+    let mut metastream: Option<Box<dyn Write + Send>> =
+        match (output.meta_file.as_deref(), output.output_prefix.as_deref()) {
+            (Some("-"), _) => Some(Box::new(io::stdout())),
+            (Some(filename), _) => Some(Box::new(File::create(filename)?)),
+            (None, Some("stdout")) => None,
+            (None, Some(filename)) => Some(Box::new(File::create(filename.to_owned() + ".out")?)),
+            (None, None) => None,
+        };
+
+    let mut gffstream: Option<Box<dyn Write + Send>> =
+        match (output.gff_file.as_deref(), output.output_prefix.as_deref()) {
+            (Some("-"), _) => Some(Box::new(io::stdout())),
+            (Some(filename), _) => Some(Box::new(File::create(filename)?)),
+            (None, Some("stdout")) => None,
+            (None, Some(filename)) => Some(Box::new(File::create(filename.to_owned() + ".gff")?)),
+            (None, None) => None,
+        };
 
     if let Some(ref mut gff) = &mut gffstream {
         writeln!(gff, "##gff-version 3")?;
+        provenance.write_comments(gff, "#!")?;
+    }
+    if let Some(ref mut meta) = &mut metastream {
+        provenance.write_comments(meta, "# ")?;
+    }
+    // the protein and DNA outputs have no comments, so their provenance is
+    // written next to them
+    for (file, extension) in [
+        (output.aa_file.as_deref(), ".faa"),
+        (output.nucleotide_file.as_deref(), ".ffn"),
+    ] {
+        let filename = match (file, output.output_prefix.as_deref()) {
+            (Some("-"), _) | (None, Some("stdout")) | (None, None) => continue,
+            (Some(filename), _) => filename.to_owned(),
+            (None, Some(prefix)) => prefix.to_owned() + extension,
+        };
+        let mut json = vec![];
+        provenance.write_json(&mut json)?;
+        std::fs::write(filename + ".json", json)?;
     }
 
     let dnastream: Option<Box<dyn Write + Send>> = match (
-        matches.value_of("nucleotide-file"),
-        matches.value_of("output-prefix"),
+        output.nucleotide_file.as_deref(),
+        output.output_prefix.as_deref(),
     ) {
         (Some("-"), _) => Some(Box::new(io::stdout())),
         (Some(filename), _) => Some(Box::new(File::create(filename)?)),
@@ -173,141 +858,815 @@ fn main() -> Result<()> {
         (None, None) => None,
     };
 
-    if aastream.is_none() && metastream.is_none() && gffstream.is_none() && dnastream.is_none() {
+    let pepstream: Option<Box<dyn Write + Send>> = match output.peptide_file.as_deref() {
+        Some("-") => Some(Box::new(io::stdout())),
+        Some(filename) => Some(Box::new(File::create(filename)?)),
+        None => None,
+    };
+
+    let pairstream: Option<Box<dyn Write + Send>> = match output.pair_file.as_deref() {
+        Some("-") => Some(Box::new(io::stdout())),
+        Some(filename) => Some(Box::new(File::create(filename)?)),
+        None => None,
+    };
+
+    let diagnosticstream: Option<Box<dyn Write + Send>> = match output.diagnostics_file.as_deref() {
+        Some("-") => Some(Box::new(io::stdout())),
+        Some(filename) => Some(Box::new(File::create(filename)?)),
+        None => None,
+    };
+
+    if aastream.is_none()
+        && metastream.is_none()
+        && gffstream.is_none()
+        && dnastream.is_none()
+        && pepstream.is_none()
+    {
         aastream = Some(Box::new(io::stdout()));
     }
 
-    if matches.is_present("unordered") {
+    let whole_genome = model.complete;
+    let long_reads = model.long_reads;
+    let soft_mask = input.soft_mask == config::SoftMask::Noncoding;
+    let gene_ids = gene::GeneIds::parse(&output.gene_id)?;
+    let mut filter = gene::GeneFilter::new(whole_genome || long_reads);
+    if let Some(min_length) = config.filter.min_gene_length {
+        filter.min_length = min_length;
+    }
+    filter.min_protein_length = config.filter.min_protein_length;
+    if let Some(min_score) = config.filter.min_score {
+        filter.min_score = min_score;
+    }
+    if let Some(max_indels) = config.filter.max_indels {
+        filter.max_indels = max_indels;
+    }
+
+    let mut digestion = peptide::Digestion::new(
+        config
+            .digestion
+            .enzyme
+            .parse()
+            .map_err(|_| anyhow!("unknown enzyme '{}'", config.digestion.enzyme))?,
+    );
+    digestion.missed_cleavages = config.digestion.missed_cleavages;
+    digestion.min_length = config.digestion.min_peptide_length;
+    digestion.max_length = config.digestion.max_peptide_length;
+
+    let mut runner = Runner::new(global, locals, threading.thread_num)?;
+    runner.whole_genome = whole_genome;
+    runner.long_reads = long_reads;
+    runner.filter = filter;
+    if model.compact_trellis {
+        runner.trellis = Trellis::Compact;
+    }
+
+    let reorder_window = match threading.reorder_window {
+        Some(window) => window,
+        None => 4 * runner.threads(),
+    };
+    if reorder_window == 0 {
+        return Err(anyhow!("--reorder-window should be at least 1"));
+    }
+    let mut batching = Batching {
+        lookahead: reorder_window,
+        ..Batching::default()
+    };
+    if let Some(bases) = threading.batch_size {
+        batching.bases = bases;
+    }
+
+    let summary = if threading.unordered {
         run(
-            global,
-            locals,
+            &runner,
             inputseqs,
+            mateseqs,
+            interleaved,
+            input.merge_mates,
+            input.reconcile,
             aastream.map(UnbufferingBuffer::new),
             metastream.map(UnbufferingBuffer::new),
             gffstream.map(UnbufferingBuffer::new),
             dnastream.map(UnbufferingBuffer::new),
-            matches.value_of("complete").unwrap() == "1",
-            matches.is_present("formatted"),
-            usize::from_str_radix(matches.value_of("thread-num").unwrap(), 10)?,
-        )?;
+            pepstream.map(UnbufferingBuffer::new),
+            pairstream.map(UnbufferingBuffer::new),
+            diagnosticstream.map(UnbufferingBuffer::new),
+            input.strict,
+            soft_mask,
+            digestion,
+            output.formatted,
+            input.full_headers,
+            &gene_ids,
+            batching,
+            None,
+        )?
     } else {
         run(
-            global,
-            locals,
+            &runner,
             inputseqs,
+            mateseqs,
+            interleaved,
+            input.merge_mates,
+            input.reconcile,
             aastream.map(SortingBuffer::new),
             metastream.map(SortingBuffer::new),
             gffstream.map(SortingBuffer::new),
             dnastream.map(SortingBuffer::new),
-            matches.value_of("complete").unwrap() == "1",
-            matches.is_present("formatted"),
-            usize::from_str_radix(matches.value_of("thread-num").unwrap(), 10)?,
-        )?;
+            pepstream.map(SortingBuffer::new),
+            pairstream.map(SortingBuffer::new),
+            diagnosticstream.map(SortingBuffer::new),
+            input.strict,
+            soft_mask,
+            digestion,
+            output.formatted,
+            input.full_headers,
+            &gene_ids,
+            batching,
+            Some(reorder_window),
+        )?
+    };
+
+    eprint!("{}", summary);
+    if let Some(filename) = &output.summary_file {
+        serde_json::to_writer_pretty(File::create(filename)?, &summary)?;
+    }
+
+    if summary.malformed_records > 0 {
+        return Err(anyhow!(
+            "the input was truncated at a malformed record, the records after it were not read"
+        ));
+    }
+    Ok(())
+}
+
+fn evaluate(matches: &ArgMatches) -> Result<()> {
+    let predictions = evaluate::read_predictions(BufReader::new(File::open(
+        matches.value_of("predictions").unwrap(),
+    )?))?;
+    let mut annotations = match matches.value_of("annotations") {
+        Some(filename) => evaluate::read_gff(
+            BufReader::new(File::open(filename)?),
+            matches.value_of("feature").unwrap(),
+        )?,
+        None => evaluate::read_mapping(BufReader::new(File::open(
+            matches.value_of("mapping").unwrap(),
+        )?))?,
+    };
+    if let Some(filename) = matches.value_of("seq-file") {
+        annotations.lengths = evaluate::read_lengths(BufReader::new(File::open(filename)?))?;
+    }
+
+    let evaluation =
+        evaluate::evaluate(&annotations.genes, &predictions.genes, &annotations.lengths);
+    if matches.is_present("json") {
+        serde_json::to_writer_pretty(io::stdout(), &evaluation)?;
+        println!();
+    } else {
+        print!("{}", evaluation);
+    }
+    Ok(())
+}
+
+fn train(matches: &ArgMatches) -> Result<()> {
+    let mut annotations = evaluate::read_gff(
+        BufReader::new(File::open(matches.value_of("annotations").unwrap())?),
+        matches.value_of("feature").unwrap(),
+    )?;
+    if let Some(filename) = matches.value_of("seq-file") {
+        annotations.lengths = evaluate::read_lengths(BufReader::new(File::open(filename)?))?;
+    }
+    let structure = train::Structure::count(&annotations)?;
+    eprint!("{}", structure);
+
+    let (train_dir, train_file) = training_file(
+        matches.value_of("train-file").unwrap(),
+        matches.value_of("train-file-dir"),
+    )?;
+    let lines = hmm::transition_lines(train_dir, train_file)?;
+    let mut output: Box<dyn Write> = match matches.value_of("output") {
+        Some(filename) => Box::new(File::create(filename)?),
+        None => Box::new(io::stdout()),
+    };
+    for line in train::replace_transitions(&lines, &structure.transitions()) {
+        writeln!(output, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn validate_model(matches: &ArgMatches) -> Result<()> {
+    let (train_dir, train_file) = training_file(
+        matches.value_of("train-file").unwrap(),
+        matches.value_of("train-file-dir"),
+    )?;
+    let (global, locals) = hmm::get_train_from_file(train_dir, train_file)?;
+    let problems = hmm::validate(&global, &locals);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    match problems.len() {
+        0 => {
+            println!("{}: OK", matches.value_of("train-file").unwrap());
+            Ok(())
+        }
+        n => Err(anyhow!("{} problems in the training file", n)),
     }
+}
 
+fn stats(matches: &ArgMatches) -> Result<()> {
+    let input: Box<dyn Read + Send> = match matches.value_of("seq-file").unwrap() {
+        "stdin" => Box::new(io::stdin()),
+        filename => Box::new(File::open(filename)?),
+    };
+    let mut stats = stats::SequenceStats::default();
+    for record in records(input)? {
+        stats.add(&record?.seq);
+    }
+    stats.finish();
+    if matches.is_present("json") {
+        serde_json::to_writer_pretty(io::stdout(), &stats)?;
+        println!();
+    } else {
+        print!("{}", stats);
+    }
+    Ok(())
+}
+
+fn convert(matches: &ArgMatches) -> Result<()> {
+    let input: Box<dyn BufRead> = match matches.value_of("input").unwrap() {
+        "stdin" => Box::new(BufReader::new(io::stdin())),
+        filename => Box::new(BufReader::new(File::open(filename)?)),
+    };
+    let output: Box<dyn Write> = match matches.value_of("output") {
+        Some(filename) => Box::new(File::create(filename)?),
+        None => Box::new(io::stdout()),
+    };
+    convert::convert(
+        input,
+        io::BufWriter::new(output),
+        matches.value_of("format").unwrap().parse()?,
+        &gene::GeneIds::parse(matches.value_of("gene-id").unwrap())?,
+    )?;
     Ok(())
 }
 
 fn run<R: Read + Send, W: WritingBuffer + Send>(
-    global: Box<hmm::Global>,
-    locals: Vec<hmm::Local>,
+    runner: &Runner,
     inputseqs: R,
+    mateseqs: Option<R>,
+    interleaved: bool,
+    merge_mates: bool,
+    reconcile: bool,
     aa_buffer: Option<W>,
     meta_buffer: Option<W>,
     gff_buffer: Option<W>,
     dna_buffer: Option<W>,
-    whole_genome: bool,
+    pep_buffer: Option<W>,
+    pair_buffer: Option<W>,
+    diagnostic_buffer: Option<W>,
+    strict: bool,
+    soft_mask: bool,
+    digestion: peptide::Digestion,
     formatted: bool,
-    thread_num: usize,
-) -> Result<()> {
-    ThreadPoolBuilder::new()
-        .num_threads(thread_num)
-        .build_global()?;
-
+    full_headers: bool,
+    gene_ids: &gene::GeneIds,
+    batching: Batching,
+    reorder_window: Option<usize>,
+) -> Result<Summary> {
     let meta_buffer = meta_buffer.map(Mutex::new);
     let gff_buffer = gff_buffer.map(Mutex::new);
     let dna_buffer = dna_buffer.map(Mutex::new);
     let aa_buffer = aa_buffer.map(Mutex::new);
+    let pep_buffer = pep_buffer.map(Mutex::new);
+    let pair_buffer = pair_buffer.map(Mutex::new);
+    let diagnostic_buffer = diagnostic_buffer.map(Mutex::new);
+    let summary = Mutex::new(Summary::default());
+    let window = reorder_window.map(ReorderWindow::new);
 
-    Chunked::new(100, fasta::Reader::new(inputseqs).into_records())
-        .enumerate()
-        .par_bridge()
-        .map(|(index, recordvec)| {
-            let mut metabuf = Vec::new();
-            let mut gffbuf = Vec::new();
-            let mut dnabuf = Vec::new();
-            let mut aabuf = Vec::new();
-            for record in recordvec {
-                let fasta::OwnedRecord { mut head, seq } = record?;
-                head = head.into_iter().take_while(u8::is_ascii_graphic).collect();
-                let nseq: Vec<Nuc> = seq
-                    .iter()
-                    .map(u8::to_ascii_uppercase)
-                    .map(Nuc::from)
-                    .collect();
-                let read_prediction = if nseq.is_empty() {
-                    gene::ReadPrediction::new(head)
-                } else {
-                    viterbi(&global, &locals, head, nseq, whole_genome)
+    let complete_genes = runner.complete_genes();
+
+    let paired = interleaved || mateseqs.is_some();
+    let records: Box<dyn Iterator<Item = Result<Vec<Record>>> + Send> = if paired {
+        Box::new(Mates {
+            first: records(inputseqs)?,
+            second: mateseqs.map(records).transpose()?,
+        })
+    } else {
+        Box::new(records(inputseqs)?.map(|record| Ok(vec![record?])))
+    };
+
+    runner.install(|| {
+        batching
+            .batches(records, |records| match records {
+                Ok(records) => records.iter().map(|record| record.seq.len()).sum(),
+                Err(_) => 0,
+            })
+            .inspect(|(index, _)| {
+                if let Some(window) = &window {
+                    window.wait(*index);
+                }
+            })
+            .par_bridge()
+            .map(|(index, recordvec)| {
+                let _slot = window.as_ref().map(|window| window.slot(index));
+                let mut metabuf = Vec::new();
+                let mut gffbuf = Vec::new();
+                let mut dnabuf = Vec::new();
+                let mut aabuf = Vec::new();
+                let mut pepbuf = Vec::new();
+                let mut pairbuf = Vec::new();
+                let mut chunk_summary = Summary::default();
+                let mut diagnostics = Diagnostics {
+                    strict,
+                    buffer: diagnostic_buffer.as_ref().map(|_| Vec::new()),
+                };
+                let mut output = |read_prediction: &gene::ReadPrediction| -> Result<()> {
+                    if meta_buffer.is_some() {
+                        read_prediction.meta(&mut metabuf, gene_ids)?;
+                    }
+                    if gff_buffer.is_some() {
+                        read_prediction.gff(&mut gffbuf, gene_ids)?;
+                    }
+                    if dna_buffer.is_some() {
+                        read_prediction.dna(&mut dnabuf, formatted, gene_ids)?;
+                    }
+                    if aa_buffer.is_some() {
+                        read_prediction.protein(&mut aabuf, complete_genes, gene_ids)?;
+                    }
+                    if pep_buffer.is_some() {
+                        read_prediction.peptides(&mut pepbuf, complete_genes, &digestion)?;
+                    }
+                    Ok(())
                 };
-                if meta_buffer.is_some() {
-                    read_prediction.meta(&mut metabuf)?;
+                if !paired {
+                    // Single reads of a batch are decoded one after the other,
+                    // reusing the buffers of the decoder. Diagnostics found while
+                    // reading are kept to be reported in order with those of the
+                    // decoder.
+                    let mut reads = Vec::with_capacity(recordvec.len());
+                    let mut entries = Vec::with_capacity(recordvec.len());
+                    for records in recordvec {
+                        let mut found = vec![];
+                        match records {
+                            Ok(records) => {
+                                for record in records {
+                                    let Prepared {
+                                        head,
+                                        description,
+                                        seq,
+                                        mask,
+                                        ..
+                                    } = prepare(record, None, full_headers, soft_mask, &mut |d| {
+                                        found.push(d);
+                                        Ok(())
+                                    })?;
+                                    let read = (head.clone(), description, seq.len());
+                                    entries.push((std::mem::take(&mut found), Some(read)));
+                                    reads.push((head, seq, mask));
+                                }
+                            }
+                            Err(e) => {
+                                found.push(Diagnostic::new(None, Kind::MalformedRecord, e));
+                                entries.push((found, None));
+                                chunk_summary.malformed_records += 1;
+                            }
+                        }
+                    }
+                    let mut results = runner.decode_reusing(reads).into_iter();
+                    for (found, read) in entries {
+                        for diagnostic in found {
+                            diagnostics.report(diagnostic)?;
+                        }
+                        if let Some((head, description, length)) = read {
+                            let mut read_prediction = reported(
+                                results.next().unwrap(),
+                                head,
+                                &mut chunk_summary,
+                                &mut diagnostics,
+                            )?;
+                            read_prediction.description = description;
+                            chunk_summary.add(&read_prediction, length);
+                            output(&read_prediction)?;
+                        }
+                    }
+                } else {
+                    for records in recordvec {
+                        let records = match records {
+                            Ok(records) => records,
+                            Err(e) => {
+                                diagnostics.report(Diagnostic::new(
+                                    None,
+                                    Kind::MalformedRecord,
+                                    e,
+                                ))?;
+                                chunk_summary.malformed_records += 1;
+                                continue;
+                            }
+                        };
+                        let mut reads = Vec::with_capacity(2);
+                        for (mate, record) in records.into_iter().enumerate() {
+                            reads.push(prepare(
+                                record,
+                                Some(mate + 1),
+                                full_headers,
+                                soft_mask,
+                                &mut |d| diagnostics.report(d),
+                            )?);
+                        }
+
+                        let offset = match &reads[..] {
+                            [read1, read2] if merge_mates || reconcile || pair_buffer.is_some() => {
+                                pair::overlap(&read1.seq, &read2.seq)
+                            }
+                            _ => None,
+                        };
+
+                        let mut predictions = Vec::with_capacity(2);
+                        match (&mut reads[..], offset) {
+                            ([read1, read2], Some(offset)) if merge_mates => {
+                                let fragment = pair::merge(
+                                    &read1.seq,
+                                    read1.qual.as_deref(),
+                                    &read2.seq,
+                                    read2.qual.as_deref(),
+                                    offset,
+                                );
+                                let mask = pair::merge_mask(&read1.mask, &read2.mask, offset);
+                                let head = pair::pair_head(read1.head.clone());
+                                let length = fragment.len();
+                                let joint = decode(
+                                    runner,
+                                    head,
+                                    fragment,
+                                    &mask,
+                                    &mut chunk_summary,
+                                    &mut diagnostics,
+                                )?;
+                                let (mut mate1, mut mate2) = pair::project(
+                                    &joint,
+                                    std::mem::take(&mut read1.head),
+                                    std::mem::take(&mut read2.head),
+                                    read1.seq.len(),
+                                    read2.seq.len(),
+                                    offset,
+                                );
+                                mate1.description = std::mem::take(&mut read1.description);
+                                mate2.description = std::mem::take(&mut read2.description);
+                                // the genes of the fragment are counted once,
+                                // not for each mate they are reported on
+                                chunk_summary.add(&joint, length);
+                                predictions.push(mate1);
+                                predictions.push(mate2);
+                            }
+                            (reads, _) => {
+                                for read in reads {
+                                    let head = std::mem::take(&mut read.head);
+                                    let length = read.seq.len();
+                                    // the sequences of the mates are still needed
+                                    // to reconcile them and to compare their genes
+                                    let mut read_prediction = decode(
+                                        runner,
+                                        head,
+                                        read.seq.clone(),
+                                        &read.mask,
+                                        &mut chunk_summary,
+                                        &mut diagnostics,
+                                    )?;
+                                    read_prediction.description =
+                                        std::mem::take(&mut read.description);
+                                    chunk_summary.add(&read_prediction, length);
+                                    predictions.push(read_prediction);
+                                }
+                            }
+                        }
+                        for read_prediction in &predictions {
+                            output(read_prediction)?;
+                        }
+
+                        if let ([read1, read2], [mate1, mate2]) = (&reads[..], &predictions[..]) {
+                            if pair_buffer.is_some() {
+                                writeln!(
+                                    pairbuf,
+                                    "{}\t{}\t{}\t{}",
+                                    String::from_utf8_lossy(&mate1.head),
+                                    String::from_utf8_lossy(&mate2.head),
+                                    offset
+                                        .map_or(".".to_string(), |offset| (offset + 1).to_string()),
+                                    pair::agreement(mate1, mate2, read2.seq.len(), offset),
+                                )?;
+                            }
+                            if let (true, false, Some(offset)) = (reconcile, merge_mates, offset) {
+                                let fragment = pair::merge(
+                                    &read1.seq,
+                                    read1.qual.as_deref(),
+                                    &read2.seq,
+                                    read2.qual.as_deref(),
+                                    offset,
+                                );
+                                let mask = pair::merge_mask(&read1.mask, &read2.mask, offset);
+                                let head = pair::pair_head(mate1.head.clone());
+                                let mut joint = decode(
+                                    runner,
+                                    head,
+                                    fragment,
+                                    &mask,
+                                    &mut chunk_summary,
+                                    &mut diagnostics,
+                                )?;
+                                joint.description = mate1.description.clone();
+                                output(&joint)?;
+                            }
+                        }
+                    }
                 }
-                if gff_buffer.is_some() {
-                    read_prediction.gff(&mut gffbuf)?;
+                if let Some(buffer) = &meta_buffer {
+                    buffer.lock().unwrap().add(index, metabuf)?;
                 }
-                if dna_buffer.is_some() {
-                    read_prediction.dna(&mut dnabuf, formatted)?;
+                if let Some(buffer) = &gff_buffer {
+                    buffer.lock().unwrap().add(index, gffbuf)?;
                 }
-                if aa_buffer.is_some() {
-                    read_prediction.protein(&mut aabuf, whole_genome)?;
+                if let Some(buffer) = &dna_buffer {
+                    buffer.lock().unwrap().add(index, dnabuf)?;
                 }
+                if let Some(buffer) = &aa_buffer {
+                    buffer.lock().unwrap().add(index, aabuf)?;
+                }
+                if let Some(buffer) = &pep_buffer {
+                    buffer.lock().unwrap().add(index, pepbuf)?;
+                }
+                if let Some(buffer) = &pair_buffer {
+                    buffer.lock().unwrap().add(index, pairbuf)?;
+                }
+                if let (Some(buffer), Some(diagbuf)) = (&diagnostic_buffer, diagnostics.buffer) {
+                    buffer.lock().unwrap().add(index, diagbuf)?;
+                }
+                summary.lock().unwrap().merge(chunk_summary);
+                Ok(())
+            })
+            .collect::<Result<()>>()
+    })?;
+
+    Ok(summary.into_inner().unwrap())
+}
+
+/// Problems with individual reads found while processing a batch.
+struct Diagnostics {
+    strict: bool,
+    buffer: Option<Vec<u8>>,
+}
+
+impl Diagnostics {
+    /// Fails in strict mode, and otherwise writes the diagnostic to the
+    /// buffer, or to standard error if there is none.
+    fn report(&mut self, diagnostic: Diagnostic) -> Result<()> {
+        if self.strict {
+            return Err(anyhow!("{}", diagnostic));
+        }
+        match &mut self.buffer {
+            Some(buffer) => diagnostic.tsv(buffer),
+            None => eprintln!("Warning: {}", diagnostic),
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a sequence with the runner. Sequences that cannot be decoded are
+/// reported and output without genes.
+fn decode(
+    runner: &Runner,
+    head: Vec<u8>,
+    seq: Vec<Nuc>,
+    mask: &[bool],
+    summary: &mut Summary,
+    diagnostics: &mut Diagnostics,
+) -> Result<gene::ReadPrediction> {
+    let result = runner.decode(head.clone(), seq, mask);
+    reported(result, head, summary, diagnostics)
+}
+
+/// Reports the problems found while decoding the read with header `head`.
+fn reported(
+    result: Result<gene::ReadPrediction, viterbi::ReadError>,
+    head: Vec<u8>,
+    summary: &mut Summary,
+    diagnostics: &mut Diagnostics,
+) -> Result<gene::ReadPrediction> {
+    match result {
+        Ok(read_prediction) => {
+            if read_prediction.none_states > 0 {
+                diagnostics.report(Diagnostic::new(
+                    Some(&head),
+                    Kind::NoneState,
+                    format!(
+                        "{} positions replaced by the non-coding state",
+                        read_prediction.none_states
+                    ),
+                ))?;
             }
-            if let Some(buffer) = &meta_buffer {
-                buffer.lock().unwrap().add(index, metabuf)?;
-            }
-            if let Some(buffer) = &gff_buffer {
-                buffer.lock().unwrap().add(index, gffbuf)?;
-            }
-            if let Some(buffer) = &dna_buffer {
-                buffer.lock().unwrap().add(index, dnabuf)?;
-            }
-            if let Some(buffer) = &aa_buffer {
-                buffer.lock().unwrap().add(index, aabuf)?;
-            }
-            Ok(())
-        })
-        .collect()
+            Ok(read_prediction)
+        }
+        Err(e) => {
+            diagnostics.report(Diagnostic::new(Some(&head), Kind::SkippedSequence, e))?;
+            summary.skipped_sequences += 1;
+            Ok(gene::ReadPrediction::new(head))
+        }
+    }
 }
 
-struct Chunked<I: Iterator> {
-    size: usize,
-    iterator: I,
+struct Record {
+    head: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Option<Vec<u8>>,
+}
+
+/// A read with its header, description, nucleotides, qualities and mask.
+struct Prepared {
+    head: Vec<u8>,
+    description: Vec<u8>,
+    seq: Vec<Nuc>,
+    qual: Option<Vec<u8>>,
+    mask: Vec<bool>,
 }
 
-impl<I: Iterator> Chunked<I> {
-    fn new(size: usize, iterator: I) -> Self {
-        Chunked { size, iterator }
+/// Splits the header of a record in its identifier and description (kept
+/// only for `full_headers`), marks the identifier with the number of the
+/// `mate` if paired, and converts the sequence. Invalid headers are passed
+/// to `report` and converted lossily.
+fn prepare(
+    record: Record,
+    mate: Option<usize>,
+    full_headers: bool,
+    soft_mask: bool,
+    report: &mut dyn FnMut(Diagnostic) -> Result<()>,
+) -> Result<Prepared> {
+    let Record {
+        mut head,
+        seq,
+        qual,
+    } = record;
+    let id_length = head.iter().take_while(|c| c.is_ascii_graphic()).count();
+    let mut description = if full_headers {
+        let rest = &head[id_length..];
+        let start = rest.iter().take_while(|c| c.is_ascii_whitespace()).count();
+        rest[start..].to_vec()
+    } else {
+        vec![]
+    };
+    head.truncate(id_length);
+    if let Some(mate) = mate {
+        head = pair::mate_head(head, mate);
+    }
+    if let Err(e) = std::str::from_utf8(&head) {
+        report(Diagnostic::new(Some(&head), Kind::InvalidHeader, e))?;
+        head = String::from_utf8_lossy(&head).into_owned().into_bytes();
+    }
+    if let Err(e) = std::str::from_utf8(&description) {
+        report(Diagnostic::new(Some(&head), Kind::InvalidHeader, e))?;
+        description = String::from_utf8_lossy(&description)
+            .into_owned()
+            .into_bytes();
+    }
+    let nseq: Vec<Nuc> = seq
+        .iter()
+        .map(u8::to_ascii_uppercase)
+        .map(Nuc::from)
+        .collect();
+    let mask: Vec<bool> = if soft_mask {
+        seq.iter().map(u8::is_ascii_lowercase).collect()
+    } else {
+        vec![]
+    };
+    Ok(Prepared {
+        head,
+        description,
+        seq: nseq,
+        qual,
+        mask,
+    })
+}
+
+/// Reads records in fastq format if the input starts with `@`, or fasta otherwise.
+fn records<'a, R: Read + Send + 'a>(
+    input: R,
+) -> Result<Box<dyn Iterator<Item = Result<Record>> + Send + 'a>> {
+    let mut input = BufReader::new(input);
+    if input.fill_buf()?.first() == Some(&b'@') {
+        Ok(Box::new(fastq::Reader::new(input).into_records().map(
+            |record| {
+                let fastq::OwnedRecord { head, seq, qual } = record?;
+                Ok(Record {
+                    head,
+                    seq,
+                    qual: Some(qual),
+                })
+            },
+        )))
+    } else {
+        Ok(Box::new(fasta::Reader::new(input).into_records().map(
+            |record| {
+                let fasta::OwnedRecord { head, seq } = record?;
+                Ok(Record {
+                    head,
+                    seq,
+                    qual: None,
+                })
+            },
+        )))
     }
 }
 
-impl<I: Iterator> Iterator for Chunked<I> {
-    type Item = Vec<I::Item>;
+/// Pairs of mates, from two files or from consecutive records of one file.
+struct Mates<I> {
+    first: I,
+    second: Option<I>,
+}
+
+impl<I: Iterator<Item = Result<Record>>> Iterator for Mates<I> {
+    type Item = Result<Vec<Record>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut items = Vec::with_capacity(self.size);
-        for _ in 0..self.size {
-            if let Some(item) = self.iterator.next() {
-                items.push(item);
-            } else {
-                break;
+        let first = match self.first.next() {
+            Some(first) => first,
+            None => {
+                return self
+                    .second
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map(|_| Err(anyhow!("the mate file has more records")))
             }
+        };
+        let second = match &mut self.second {
+            Some(second) => second.next(),
+            None => self.first.next(),
+        };
+        Some(match (first, second) {
+            (Ok(first), Some(Ok(second))) => Ok(vec![first, second]),
+            (Err(e), _) | (_, Some(Err(e))) => Err(e),
+            (Ok(first), None) => Err(anyhow!(
+                "no mate for {}",
+                String::from_utf8_lossy(&first.head)
+            )),
+        })
+    }
+}
+
+/// Keeps the reader from getting more than a fixed number of batches ahead
+/// of the oldest batch that was not written yet, so that the sorting
+/// buffers stay bounded.
+struct ReorderWindow {
+    size: usize,
+    /// The oldest unfinished batch and which of the later batches finished.
+    state: Mutex<(usize, VecDeque<bool>)>,
+    finished: Condvar,
+}
+
+impl ReorderWindow {
+    fn new(size: usize) -> Self {
+        ReorderWindow {
+            size,
+            state: Mutex::new((0, VecDeque::new())),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Blocks until the batch with this index fits in the window.
+    fn wait(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        while index >= state.0 + self.size {
+            state = self.finished.wait(state).unwrap();
+        }
+    }
+
+    /// Marks the batch with this index as finished when the slot is dropped,
+    /// also if processing it failed.
+    fn slot(&self, index: usize) -> WindowSlot<'_> {
+        WindowSlot {
+            window: self,
+            index,
         }
-        if items.is_empty() {
-            None
-        } else {
-            Some(items)
+    }
+
+    fn finish(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        let (next, finished) = &mut *state;
+        while *next + finished.len() <= index {
+            finished.push_back(false);
         }
+        finished[index - *next] = true;
+        while finished.front() == Some(&true) {
+            finished.pop_front();
+            *next += 1;
+        }
+        self.finished.notify_all();
+    }
+}
+
+struct WindowSlot<'a> {
+    window: &'a ReorderWindow,
+    index: usize,
+}
+
+impl Drop for WindowSlot<'_> {
+    fn drop(&mut self) {
+        self.window.finish(self.index);
     }
 }
 
@@ -326,7 +1685,7 @@ impl<W: Write + Send> SortingBuffer<W> {
         SortingBuffer {
             next: 0,
             queue: VecDeque::new(),
-            stream: stream,
+            stream,
         }
     }
 }
@@ -363,3 +1722,160 @@ impl<W: Write + Send> WritingBuffer for UnbufferingBuffer<W> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    /// FASTA or FASTQ records with arbitrary headers, sequences and
+    /// qualities, or arbitrary bytes.
+    fn input() -> impl Strategy<Value = Vec<u8>> {
+        let record = (
+            prop::bool::ANY,
+            prop::collection::vec(any::<u8>(), 0..40),
+            "[ACGTNRYacgtn\r\n]{0,200}",
+            "[!-~]{0,200}",
+        )
+            .prop_map(|(fastq, head, seq, qual)| {
+                let mut record = vec![if fastq { b'@' } else { b'>' }];
+                record.extend(head);
+                record.push(b'\n');
+                record.extend(seq.bytes());
+                if fastq {
+                    record.extend(b"\n+\n");
+                    record.extend(qual.bytes());
+                }
+                record.push(b'\n');
+                record
+            });
+        prop_oneof![
+            prop::collection::vec(record, 0..10).prop_map(|records| records.concat()),
+            prop::collection::vec(any::<u8>(), 0..1000),
+        ]
+    }
+
+    fn parse(args: &[&str]) -> ArgMatches<'static> {
+        let args = args.iter().map(OsString::from).collect();
+        app()
+            .get_matches_from_safe(with_default_subcommand(args))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_subcommand() {
+        let matches = parse(&["FragGeneScanRs", "-s", "reads.fa", "-t", "complete"]);
+        assert_eq!(matches.subcommand_name(), Some("predict"));
+        let matches = parse(&["FragGeneScanRs", "predict", "-t", "complete"]);
+        assert_eq!(matches.subcommand_name(), Some("predict"));
+        let matches = parse(&["FragGeneScanRs", "stats", "-s", "reads.fa"]);
+        assert_eq!(matches.subcommand_name(), Some("stats"));
+    }
+
+    #[test]
+    fn test_complete() {
+        for (args, expected) in [
+            (&["-w"][..], true),
+            (&["-w", "1"], true),
+            (&["-w", "0"], false),
+            (&["--complete", "-s", "genome.fa"], true),
+            (&[], false),
+        ] {
+            let mut all = vec!["FragGeneScanRs", "-t", "complete"];
+            all.extend(args);
+            let matches = parse(&all);
+            let config = configuration(matches.subcommand_matches("predict").unwrap()).unwrap();
+            assert_eq!(config.model.complete, expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_typed_arguments() {
+        let args = ["FragGeneScanRs", "-t", "complete", "-p", "many"];
+        let args = args.iter().map(OsString::from).collect();
+        assert!(app()
+            .get_matches_from_safe(with_default_subcommand(args))
+            .is_err());
+        assert!(training_file("sanger", None).is_err());
+        assert!(training_file("sanger", Some("models")).is_ok());
+
+        // custom training files in the default directory are found without -r
+        let dir = tempfile::tempdir().unwrap();
+        File::create(dir.path().join("custom")).unwrap();
+        assert_eq!(
+            (dir.path().to_path_buf(), PathBuf::from("custom")),
+            training_file_in(dir.path(), "custom", None).unwrap()
+        );
+        assert!(training_file_in(dir.path(), "other", None).is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        for option in ["--match-deletion", "--deleted-nucleotide"] {
+            for value in ["0", "-0.1", "1.5", "NaN", "half"] {
+                let args = ["FragGeneScanRs", "-t", "454_10", option, value];
+                let args = args.iter().map(OsString::from).collect();
+                assert!(
+                    app()
+                        .get_matches_from_safe(with_default_subcommand(args))
+                        .is_err(),
+                    "{} {}",
+                    option,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_configuration() {
+        let file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        std::fs::write(
+            file.path(),
+            "[model]\ntraining-file = \"sanger_5\"\n[threading]\nthread-num = 4\nunordered = true\n",
+        )
+        .unwrap();
+        let matches = parse(&[
+            "FragGeneScanRs",
+            "--preset",
+            "genome",
+            "--config",
+            file.path().to_str().unwrap(),
+            "-p",
+            "2",
+        ]);
+        let config = configuration(matches.subcommand_matches("predict").unwrap()).unwrap();
+        assert_eq!(config.model.training_file.as_deref(), Some("sanger_5"));
+        assert!(config.model.complete);
+        assert_eq!(config.threading.thread_num, 2);
+        assert!(config.threading.unordered);
+        assert_eq!(config.digestion.min_peptide_length, 5);
+    }
+
+    proptest! {
+        #[test]
+        fn test_arbitrary_input(input in input(), full_headers: bool, soft_mask: bool) {
+            for record in records(&input[..]).unwrap() {
+                let record = match record {
+                    Ok(record) => record,
+                    Err(_) => continue,
+                };
+                let length = record.seq.len();
+                let mut found = vec![];
+                let Prepared { head, description, seq: nseq, mask, .. } =
+                    prepare(record, Some(1), full_headers, soft_mask, &mut |d| {
+                        found.push(d);
+                        Ok(())
+                    })
+                    .unwrap();
+                prop_assert!(std::str::from_utf8(&head).is_ok());
+                prop_assert!(std::str::from_utf8(&description).is_ok());
+                prop_assert!(full_headers || description.is_empty());
+                prop_assert_eq!(length, nseq.len());
+                prop_assert_eq!(if soft_mask { length } else { 0 }, mask.len());
+                prop_assert!(found.iter().all(|d| d.kind == Kind::InvalidHeader));
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Configuration of a prediction run, as read from and written to TOML.
+
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+extern crate thiserror;
+use thiserror::Error;
+
+extern crate toml;
+use toml::{Table, Value};
+
+use crate::gene::GeneIds;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("invalid configuration")]
+    Parse(#[from] toml::de::Error),
+    #[error("could not write configuration")]
+    Write(#[from] toml::ser::Error),
+    #[error("unknown preset '{0}'")]
+    UnknownPreset(String),
+}
+
+/// Presets for common kinds of input, applied before the configuration
+/// file and the command line options.
+pub const PRESETS: [(&str, &str); 2] = [
+    ("short-reads", "[model]\ntraining-file = \"illumina_5\"\n"),
+    (
+        "genome",
+        "[model]\ntraining-file = \"complete\"\ncomplete = true\n",
+    ),
+];
+
+/// All options of a prediction run. Each section corresponds to a group
+/// of command line options, with their long names as keys.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Version of FragGeneScanRs that wrote the configuration.
+    pub version: Option<String>,
+    /// Checksum of the model, as computed by `hmm::checksum`. A run with a
+    /// different model fails.
+    pub model_checksum: Option<String>,
+    pub model: ModelConfig,
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    pub filter: FilterConfig,
+    pub digestion: DigestionConfig,
+    pub threading: ThreadingConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ModelConfig {
+    pub training_file: Option<String>,
+    pub train_file_dir: Option<String>,
+    pub complete: bool,
+    pub long_reads: bool,
+    pub match_insertion: Option<f64>,
+    pub match_deletion: Option<f64>,
+    pub insertion_insertion: Option<f64>,
+    pub deletion_deletion: Option<f64>,
+    pub deleted_nucleotide: Option<f64>,
+    pub max_deletion_length: usize,
+    pub compact_trellis: bool,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        ModelConfig {
+            training_file: None,
+            train_file_dir: None,
+            complete: false,
+            long_reads: false,
+            match_insertion: None,
+            match_deletion: None,
+            insertion_insertion: None,
+            deletion_deletion: None,
+            deleted_nucleotide: None,
+            max_deletion_length: crate::hmm::MAX_DELETION_LENGTH,
+            compact_trellis: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SoftMask {
+    /// Treat lowercase nucleotides as uppercase.
+    Ignore,
+    /// Don't allow genes to overlap lowercase nucleotides.
+    Noncoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct InputConfig {
+    pub seq_file_name: String,
+    pub mate_file: Option<String>,
+    pub interleaved: bool,
+    pub merge_mates: bool,
+    pub reconcile: bool,
+    pub soft_mask: SoftMask,
+    pub full_headers: bool,
+    pub strict: bool,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            seq_file_name: "stdin".to_string(),
+            mate_file: None,
+            interleaved: false,
+            merge_mates: false,
+            reconcile: false,
+            soft_mask: SoftMask::Ignore,
+            full_headers: false,
+            strict: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OutputConfig {
+    pub output_prefix: Option<String>,
+    pub meta_file: Option<String>,
+    pub gff_file: Option<String>,
+    pub aa_file: Option<String>,
+    pub nucleotide_file: Option<String>,
+    pub peptide_file: Option<String>,
+    pub pair_file: Option<String>,
+    pub summary_file: Option<String>,
+    pub diagnostics_file: Option<String>,
+    pub formatted: bool,
+    pub gene_id: String,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            output_prefix: None,
+            meta_file: None,
+            gff_file: None,
+            aa_file: None,
+            nucleotide_file: None,
+            peptide_file: None,
+            pair_file: None,
+            summary_file: None,
+            diagnostics_file: None,
+            formatted: false,
+            gene_id: GeneIds::DEFAULT.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FilterConfig {
+    pub min_gene_length: Option<usize>,
+    pub min_protein_length: usize,
+    pub min_score: Option<f64>,
+    pub max_indels: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DigestionConfig {
+    pub enzyme: String,
+    pub missed_cleavages: usize,
+    pub min_peptide_length: usize,
+    pub max_peptide_length: usize,
+}
+
+impl Default for DigestionConfig {
+    fn default() -> Self {
+        DigestionConfig {
+            enzyme: "trypsin".to_string(),
+            missed_cleavages: 0,
+            min_peptide_length: 5,
+            max_peptide_length: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ThreadingConfig {
+    pub thread_num: usize,
+    pub unordered: bool,
+    pub reorder_window: Option<usize>,
+    pub batch_size: Option<usize>,
+}
+
+impl Default for ThreadingConfig {
+    fn default() -> Self {
+        ThreadingConfig {
+            thread_num: 1,
+            unordered: false,
+            reorder_window: None,
+            batch_size: None,
+        }
+    }
+}
+
+/// Overrides the values in `base` with those in `layer`, merging tables
+/// recursively.
+pub fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parses a layer of the configuration, without filling in defaults.
+pub fn parse(content: &str) -> Result<Table, ConfigError> {
+    Ok(content.parse::<Table>()?)
+}
+
+/// The layer of the configuration of a preset.
+pub fn preset(name: &str) -> Result<Table, ConfigError> {
+    let (_, content) = PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .ok_or_else(|| ConfigError::UnknownPreset(name.to_owned()))?;
+    parse(content)
+}
+
+impl Config {
+    /// The configuration of the merged layers, with defaults for the
+    /// missing values.
+    pub fn from_table(table: Table) -> Result<Self, ConfigError> {
+        Ok(Value::Table(table).try_into()?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, ConfigError> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers() {
+        let mut table = preset("genome").unwrap();
+        merge(
+            &mut table,
+            parse("[model]\ntraining-file = \"sanger_5\"\n[threading]\nthread-num = 4\n").unwrap(),
+        );
+        let config = Config::from_table(table).unwrap();
+        assert_eq!(config.model.training_file.as_deref(), Some("sanger_5"));
+        assert!(config.model.complete);
+        assert_eq!(config.threading.thread_num, 4);
+        assert_eq!(config.digestion, DigestionConfig::default());
+
+        let written = config.to_toml().unwrap();
+        assert_eq!(
+            Config::from_table(parse(&written).unwrap()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for content in [
+            "[model]\ntraining = \"complete\"\n",
+            "[threading]\nthread-num = -1\n",
+            "[input]\nsoft-mask = \"lowercase\"\n",
+        ] {
+            let table = parse(content).unwrap();
+            assert!(Config::from_table(table).is_err(), "{}", content);
+        }
+        for (name, _) in PRESETS {
+            Config::from_table(preset(name).unwrap()).unwrap();
+        }
+        assert!(preset("illumina").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Conversion of the metadata (`.out`) output to other annotation formats.
+
+use std::io::{self, BufRead, Write};
+
+extern crate strum;
+use strum_macros::{EnumString, EnumVariantNames};
+
+extern crate thiserror;
+use thiserror::Error;
+
+use crate::gene::{Gene, GeneIds};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    /// GFF3, as written with `--gff-file`.
+    Gff,
+    /// BED6, with 0-based start coordinates.
+    Bed,
+}
+
+#[derive(Error, Debug)]
+pub enum ConvertError {
+    #[error("could not convert metadata")]
+    Io(#[from] io::Error),
+    #[error("malformed line {0}: '{1}'")]
+    MalformedLine(usize, String),
+}
+
+/// Parses a gene line of the metadata, with its identifier if it was
+/// written with a custom `--gene-id`.
+fn parse_gene(line: &str) -> Option<(Gene, Option<&str>)> {
+    let v: Vec<&str> = line.split('\t').collect();
+    let positions = |column: usize, prefix: &str| -> Option<Vec<usize>> {
+        v.get(column)?
+            .strip_prefix(prefix)?
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse().ok())
+            .collect()
+    };
+    let gene = Gene {
+        start: v.first()?.parse().ok()?,
+        end: v.get(1)?.parse().ok()?,
+        forward_strand: match *v.get(2)? {
+            "+" => true,
+            "-" => false,
+            _ => return None,
+        },
+        frame: v.get(3)?.parse().ok()?,
+        score: v.get(4)?.parse().ok()?,
+        dna: vec![],
+        inserted: positions(5, "I:")?,
+        deleted: positions(6, "D:")?,
+    };
+    let id = match v.get(7) {
+        Some(id) => Some(id.strip_prefix("ID:")?),
+        None => None,
+    };
+    Some((gene, id))
+}
+
+/// Converts metadata to `format`, with identifiers from the metadata or
+/// else formatted with `ids`. Comments, such as the provenance, are kept.
+pub fn convert<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    format: Format,
+    ids: &GeneIds,
+) -> Result<(), ConvertError> {
+    if format == Format::Gff {
+        writeln!(writer, "##gff-version 3")?;
+    }
+    let mut head = String::new();
+    let mut n = 0;
+    let mut buf = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if let Some(h) = line.strip_prefix('>') {
+            head = h.to_owned();
+            n = 0;
+            continue;
+        } else if let Some(comment) = line.strip_prefix("# ") {
+            // provenance comments become pragmas in gff
+            match format {
+                Format::Gff => writeln!(writer, "#!{}", comment)?,
+                Format::Bed => writeln!(writer, "{}", line)?,
+            }
+            continue;
+        } else if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let (gene, id) = parse_gene(&line)
+            .ok_or_else(|| ConvertError::MalformedLine(number + 1, line.clone()))?;
+        n += 1;
+        let id = id.map_or_else(|| ids.format(&head, n, &gene), str::to_owned);
+        match format {
+            Format::Gff => gene.gff(&mut buf, &head, &id),
+            Format::Bed => writeln!(
+                buf,
+                "{}\t{}\t{}\t{}\t0\t{}",
+                head,
+                gene.start - 1,
+                gene.end,
+                id,
+                if gene.forward_strand { '+' } else { '-' },
+            )?,
+        }
+        writer.write_all(&buf)?;
+        buf.clear();
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const META: &str = "# training-file sanger_5\n\
+        >read1\n\
+        3\t152\t+\t3\t1.161278\tI:\tD:45,\n\
+        >read2\n\
+        1\t99\t-\t1\t1.2\tI:7,8,\tD:\tID:gene1\n";
+
+    #[test]
+    fn test_convert() {
+        let mut gff = vec![];
+        convert(META.as_bytes(), &mut gff, Format::Gff, &GeneIds::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(gff).unwrap(),
+            "##gff-version 3\n\
+             #!training-file sanger_5\n\
+             read1\tFGS\tCDS\t3\t152\t.\t+\t2\tID=read1_3_152_+;product=predicted protein\n\
+             read2\tFGS\tCDS\t1\t99\t.\t-\t0\tID=gene1;product=predicted protein\n"
+        );
+
+        let mut bed = vec![];
+        let ids = GeneIds::parse("{read}|{n}").unwrap();
+        convert(META.as_bytes(), &mut bed, Format::Bed, &ids).unwrap();
+        assert_eq!(
+            String::from_utf8(bed).unwrap(),
+            "# training-file sanger_5\nread1\t2\t152\tread1|1\t0\t+\nread2\t0\t99\tgene1\t0\t-\n"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(matches!(
+            convert(
+                &b">read\n1\t2\n"[..],
+                vec![],
+                Format::Gff,
+                &GeneIds::default()
+            ),
+            Err(ConvertError::MalformedLine(2, _))
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+//! Problems with individual reads, which are reported without aborting
+//! the run.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The input could not be parsed into a record.
+    MalformedRecord,
+    /// The header is not valid UTF-8 and was converted lossily.
+    InvalidHeader,
+    /// The traceback ended in None-states, replaced by the non-coding state.
+    NoneState,
+    /// The sequence could not be decoded and is reported without genes.
+    SkippedSequence,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Kind::MalformedRecord => "malformed_record",
+            Kind::InvalidHeader => "invalid_header",
+            Kind::NoneState => "none_state",
+            Kind::SkippedSequence => "skipped_sequence",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Header of the read, if known.
+    pub read: Option<Vec<u8>>,
+    pub kind: Kind,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(read: Option<&[u8]>, kind: Kind, message: impl fmt::Display) -> Self {
+        Diagnostic {
+            read: read.map(<[u8]>::to_vec),
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// Appends the diagnostic as a line with the read (`.` if unknown), the
+    /// kind and the message, separated by tabs.
+    pub fn tsv(&self, buf: &mut Vec<u8>) {
+        buf.append(
+            &mut format!(
+                "{}\t{}\t{}\n",
+                self.read
+                    .as_deref()
+                    .map_or(".".into(), String::from_utf8_lossy),
+                self.kind,
+                self.message.replace(['\t', '\n'], " "),
+            )
+            .into_bytes(),
+        );
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.read {
+            Some(read) => write!(
+                f,
+                "{} in {}: {}",
+                self.kind,
+                String::from_utf8_lossy(read),
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.kind, self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tsv() {
+        let mut buf = vec![];
+        Diagnostic::new(Some(b"read1"), Kind::NoneState, "2 positions").tsv(&mut buf);
+        Diagnostic::new(None, Kind::MalformedRecord, "expected '+'\tat line 4").tsv(&mut buf);
+        assert_eq!(
+            "read1\tnone_state\t2 positions\n.\tmalformed_record\texpected '+' at line 4\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+}
@@ -14,6 +14,17 @@ pub enum Nuc {
     G,
     T,
     N,
+    // IUPAC ambiguity codes
+    R,
+    Y,
+    S,
+    W,
+    K,
+    M,
+    B,
+    D,
+    H,
+    V,
     Ai,
     Ci,
     Gi,
@@ -31,6 +42,45 @@ impl Nuc {
             Nuc::T | Nuc::Ti => Some(3),
             Nuc::N | Nuc::Ni => None,
             Nuc::Xi => None,
+            Nuc::R
+            | Nuc::Y
+            | Nuc::S
+            | Nuc::W
+            | Nuc::K
+            | Nuc::M
+            | Nuc::B
+            | Nuc::D
+            | Nuc::H
+            | Nuc::V => None,
+        }
+    }
+
+    /// Whether this is an ambiguity code other than N.
+    pub fn is_ambiguous(&self) -> bool {
+        matches!(
+            self,
+            Nuc::R | Nuc::Y | Nuc::S | Nuc::W | Nuc::K | Nuc::M | Nuc::B | Nuc::D | Nuc::H | Nuc::V
+        )
+    }
+
+    /// The nucleotides (as in `to_int`) compatible with this one.
+    pub fn bases(&self) -> &'static [usize] {
+        match self {
+            Nuc::A | Nuc::Ai => &[0],
+            Nuc::C | Nuc::Ci => &[1],
+            Nuc::G | Nuc::Gi => &[2],
+            Nuc::T | Nuc::Ti => &[3],
+            Nuc::R => &[0, 2],
+            Nuc::Y => &[1, 3],
+            Nuc::S => &[1, 2],
+            Nuc::W => &[0, 3],
+            Nuc::K => &[2, 3],
+            Nuc::M => &[0, 1],
+            Nuc::B => &[1, 2, 3],
+            Nuc::D => &[0, 2, 3],
+            Nuc::H => &[0, 1, 3],
+            Nuc::V => &[0, 1, 2],
+            Nuc::N | Nuc::Ni | Nuc::Xi => &[0, 1, 2, 3],
         }
     }
 
@@ -42,9 +92,20 @@ impl Nuc {
             Nuc::T | Nuc::Ti => Nuc::Ti,
             Nuc::N | Nuc::Ni => Nuc::Ni,
             Nuc::Xi => Nuc::Xi,
+            Nuc::R
+            | Nuc::Y
+            | Nuc::S
+            | Nuc::W
+            | Nuc::K
+            | Nuc::M
+            | Nuc::B
+            | Nuc::D
+            | Nuc::H
+            | Nuc::V => Nuc::Ni,
         }
     }
 
+    #[allow(clippy::match_like_matches_macro)]
     pub fn is_insertion(&self) -> bool {
         match self {
             Nuc::Ai | Nuc::Ci | Nuc::Gi | Nuc::Ti | Nuc::Ni => true,
@@ -59,6 +120,16 @@ impl Nuc {
             Nuc::G => Nuc::C,
             Nuc::T => Nuc::A,
             Nuc::N => Nuc::N,
+            Nuc::R => Nuc::Y,
+            Nuc::Y => Nuc::R,
+            Nuc::S => Nuc::S,
+            Nuc::W => Nuc::W,
+            Nuc::K => Nuc::M,
+            Nuc::M => Nuc::K,
+            Nuc::B => Nuc::V,
+            Nuc::D => Nuc::H,
+            Nuc::H => Nuc::D,
+            Nuc::V => Nuc::B,
             Nuc::Ai => Nuc::Ti,
             Nuc::Ci => Nuc::Gi,
             Nuc::Gi => Nuc::Ci,
@@ -76,6 +147,16 @@ impl From<u8> for Nuc {
             b'C' => Nuc::C,
             b'G' => Nuc::G,
             b'T' => Nuc::T,
+            b'R' => Nuc::R,
+            b'Y' => Nuc::Y,
+            b'S' => Nuc::S,
+            b'W' => Nuc::W,
+            b'K' => Nuc::K,
+            b'M' => Nuc::M,
+            b'B' => Nuc::B,
+            b'D' => Nuc::D,
+            b'H' => Nuc::H,
+            b'V' => Nuc::V,
             b'a' => Nuc::Ai,
             b'c' => Nuc::Ci,
             b'g' => Nuc::Gi,
@@ -95,6 +176,16 @@ impl From<Nuc> for u8 {
             Nuc::G => b'G',
             Nuc::T => b'T',
             Nuc::N => b'N',
+            Nuc::R => b'R',
+            Nuc::Y => b'Y',
+            Nuc::S => b'S',
+            Nuc::W => b'W',
+            Nuc::K => b'K',
+            Nuc::M => b'M',
+            Nuc::B => b'B',
+            Nuc::D => b'D',
+            Nuc::H => b'H',
+            Nuc::V => b'V',
             Nuc::Ai => b'a',
             Nuc::Ci => b'c',
             Nuc::Gi => b'g',
@@ -106,7 +197,7 @@ impl From<Nuc> for u8 {
 }
 
 pub fn dna(seq: &str) -> Vec<Nuc> {
-    seq.as_bytes().into_iter().copied().map(Nuc::from).collect()
+    seq.as_bytes().iter().copied().map(Nuc::from).collect()
 }
 
 pub const CODON_CODE: [u8; TRI_ACGT] = [
@@ -123,8 +214,45 @@ pub const ANTI_CODON_CODE: [u8; TRI_ACGT] = [
     b'L', b'V', b'L', b'I', b'*', b'G', b'R', b'R', b'S', b'A', b'P', b'T', b'*', b'E', b'Q', b'K',
 ];
 
+/// Translate a codon with `code` (`CODON_CODE` or `ANTI_CODON_CODE`). If
+/// the codon contains ambiguity codes, this is the amino acid encoded by all
+/// compatible codons, B (D or N), Z (E or Q), J (I or L) or X otherwise.
+/// Codons with `N` are translated to X, as in FGS.
+pub fn translate(codon: &[Nuc], code: &[u8; TRI_ACGT]) -> u8 {
+    if let Some(i) = trinucleotide(codon) {
+        return code[i];
+    }
+    if codon
+        .iter()
+        .any(|n| matches!(n, Nuc::N | Nuc::Ni | Nuc::Xi))
+    {
+        return b'X';
+    }
+    let mut amino_acids = Vec::with_capacity(4);
+    for &a in codon[0].bases() {
+        for &b in codon[1].bases() {
+            for &c in codon[2].bases() {
+                let amino_acid = code[a * BI_ACGT + b * ACGT + c];
+                if !amino_acids.contains(&amino_acid) {
+                    amino_acids.push(amino_acid);
+                }
+            }
+        }
+    }
+    amino_acids.sort_unstable();
+    match &amino_acids[..] {
+        [amino_acid] => *amino_acid,
+        [b'D', b'N'] => b'B',
+        [b'E', b'Q'] => b'Z',
+        [b'I', b'L'] => b'J',
+        _ => b'X',
+    }
+}
+
+/// Index of the codon at the start of `n`, or None if it has an ambiguous
+/// nucleotide or `n` is shorter than a codon.
 pub fn trinucleotide(n: &[Nuc]) -> Option<usize> {
-    match n.get(0..3).unwrap() {
+    match n.get(0..3)? {
         [Nuc::A, Nuc::A, Nuc::A]
         | [Nuc::A, Nuc::A, Nuc::Ai]
         | [Nuc::A, Nuc::Ai, Nuc::A]
@@ -653,3 +781,37 @@ pub fn count_cg_content(seq: &[Nuc]) -> usize {
         max(CG_MIN, count * 100 / seq.len()) - CG_MIN,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(translate(&dna("ATG"), &CODON_CODE), b'M');
+        assert_eq!(translate(&dna("CTN"), &CODON_CODE), b'X');
+        assert_eq!(translate(&dna("GAN"), &CODON_CODE), b'X');
+        assert_eq!(translate(&dna("GAY"), &CODON_CODE), b'D');
+        assert_eq!(translate(&dna("RAT"), &CODON_CODE), b'B');
+        assert_eq!(translate(&dna("SAG"), &CODON_CODE), b'Z');
+        assert_eq!(translate(&dna("MTT"), &CODON_CODE), b'J');
+        assert_eq!(translate(&dna("TRR"), &CODON_CODE), b'X');
+        assert_eq!(translate(&dna("TAR"), &CODON_CODE), b'*');
+        // reverse strand: RTC is the reverse complement of GAY
+        assert_eq!(translate(&dna("RTC"), &ANTI_CODON_CODE), b'D');
+    }
+
+    #[test]
+    fn test_ambiguity_codes() {
+        for code in b"RYSWKMBDHV" {
+            let nuc = Nuc::from(*code);
+            assert!(nuc.is_ambiguous());
+            assert_eq!(u8::from(nuc), *code);
+            let complement = nuc.rc().bases().iter().map(|b| 3 - b).collect::<Vec<_>>();
+            let mut bases = nuc.bases().to_vec();
+            bases.reverse();
+            assert_eq!(complement, bases);
+        }
+        assert!(!Nuc::N.is_ambiguous());
+    }
+}
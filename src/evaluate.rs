@@ -0,0 +1,525 @@
+//! Accuracy of predictions compared to reference annotations.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+extern crate serde;
+use serde::Serialize;
+
+extern crate thiserror;
+use thiserror::Error;
+
+/// A gene on a sequence, in 1-based inclusive coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub seqid: String,
+    pub start: usize,
+    pub end: usize,
+    pub forward_strand: bool,
+}
+
+impl Annotation {
+    /// Position of the stop codon side of the gene.
+    fn stop(&self) -> usize {
+        if self.forward_strand {
+            self.end
+        } else {
+            self.start
+        }
+    }
+
+    /// Position of the start codon side of the gene.
+    fn start_codon(&self) -> usize {
+        if self.forward_strand {
+            self.start
+        } else {
+            self.end
+        }
+    }
+
+    /// Bit for the strand and reading frame of this gene.
+    fn frame_bit(&self) -> u8 {
+        if self.forward_strand {
+            1 << (self.start % 3)
+        } else {
+            1 << (3 + self.end % 3)
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EvaluationError {
+    #[error("could not read input")]
+    Io(#[from] io::Error),
+    #[error("malformed line {0}: '{1}'")]
+    MalformedLine(usize, String),
+}
+
+/// Genes and sequence lengths read from a file.
+#[derive(Debug, Default)]
+pub struct Annotations {
+    pub genes: Vec<Annotation>,
+    pub lengths: HashMap<String, usize>,
+}
+
+fn parse_strand(strand: &str, number: usize, line: &str) -> Result<Option<bool>, EvaluationError> {
+    match strand {
+        "+" => Ok(Some(true)),
+        "-" => Ok(Some(false)),
+        "." | "?" => Ok(None),
+        _ => Err(EvaluationError::MalformedLine(number, line.to_owned())),
+    }
+}
+
+fn parse_position(value: &str, number: usize, line: &str) -> Result<usize, EvaluationError> {
+    value
+        .parse::<usize>()
+        .map_err(|_| EvaluationError::MalformedLine(number, line.to_owned()))
+}
+
+/// Reads the features of the given type (e.g. `CDS`) from a GFF file.
+/// Sequence lengths are taken from `##sequence-region` pragmas.
+pub fn read_gff<R: BufRead>(reader: R, feature: &str) -> Result<Annotations, EvaluationError> {
+    let mut annotations = Annotations::default();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with("##FASTA") {
+            break;
+        } else if let Some(region) = line.strip_prefix("##sequence-region") {
+            let v: Vec<&str> = region.split_whitespace().collect();
+            if v.len() == 3 {
+                let length = parse_position(v[2], number + 1, &line)?;
+                annotations.lengths.insert(v[0].to_owned(), length);
+            }
+        } else if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        } else {
+            let v: Vec<&str> = line.split('\t').collect();
+            if v.len() < 8 {
+                return Err(EvaluationError::MalformedLine(number + 1, line));
+            }
+            if v[2] != feature {
+                continue;
+            }
+            if let Some(forward_strand) = parse_strand(v[6], number + 1, &line)? {
+                annotations.genes.push(Annotation {
+                    seqid: v[0].to_owned(),
+                    start: parse_position(v[3], number + 1, &line)?,
+                    end: parse_position(v[4], number + 1, &line)?,
+                    forward_strand,
+                });
+            }
+        }
+    }
+    Ok(annotations)
+}
+
+/// Reads predictions from a metadata (`.out`) file.
+pub fn read_meta<R: BufRead>(reader: R) -> Result<Annotations, EvaluationError> {
+    let mut annotations = Annotations::default();
+    let mut seqid = String::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if let Some(head) = line.strip_prefix('>') {
+            seqid = head.split_whitespace().next().unwrap_or("").to_owned();
+        } else if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        } else {
+            let v: Vec<&str> = line.split('\t').collect();
+            if v.len() < 3 {
+                return Err(EvaluationError::MalformedLine(number + 1, line));
+            }
+            if let Some(forward_strand) = parse_strand(v[2], number + 1, &line)? {
+                annotations.genes.push(Annotation {
+                    seqid: seqid.clone(),
+                    start: parse_position(v[0], number + 1, &line)?,
+                    end: parse_position(v[1], number + 1, &line)?,
+                    forward_strand,
+                });
+            }
+        }
+    }
+    Ok(annotations)
+}
+
+/// Reads the predicted genes from a `.out` file or the `CDS` features of a
+/// GFF file. The format is recognised by the first line that is not a
+/// comment, which starts with `>` in a `.out` file.
+pub fn read_predictions<R: BufRead>(mut reader: R) -> Result<Annotations, EvaluationError> {
+    let mut content = vec![];
+    reader.read_to_end(&mut content)?;
+    let meta = content
+        .split(|&c| c == b'\n')
+        .find(|line| !line.starts_with(b"#") && !line.iter().all(u8::is_ascii_whitespace))
+        .is_some_and(|line| line.starts_with(b">"));
+    if meta {
+        read_meta(&content[..])
+    } else {
+        read_gff(&content[..], "CDS")
+    }
+}
+
+/// Reads a tab-separated mapping of reads to the (parts of) genes they
+/// contain, in read coordinates: read identifier, start, end and strand.
+pub fn read_mapping<R: BufRead>(reader: R) -> Result<Annotations, EvaluationError> {
+    let mut annotations = Annotations::default();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let v: Vec<&str> = line.split('\t').collect();
+        if v.len() < 4 {
+            return Err(EvaluationError::MalformedLine(number + 1, line));
+        }
+        if let Some(forward_strand) = parse_strand(v[3], number + 1, &line)? {
+            annotations.genes.push(Annotation {
+                seqid: v[0].to_owned(),
+                start: parse_position(v[1], number + 1, &line)?,
+                end: parse_position(v[2], number + 1, &line)?,
+                forward_strand,
+            });
+        }
+    }
+    Ok(annotations)
+}
+
+/// Reads the sequence lengths from a FASTA file, identified by the first
+/// word of their header.
+pub fn read_lengths<R: BufRead>(reader: R) -> Result<HashMap<String, usize>, EvaluationError> {
+    let mut lengths = HashMap::new();
+    let mut seqid: Option<String> = None;
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(head) = line.strip_prefix('>') {
+            let id = head.split_whitespace().next().unwrap_or("").to_owned();
+            lengths.insert(id.clone(), 0);
+            seqid = Some(id);
+        } else if let Some(id) = &seqid {
+            *lengths.get_mut(id).unwrap() += line.trim_end().len();
+        }
+    }
+    Ok(lengths)
+}
+
+/// The fraction `numerator / denominator`, which is undefined for an empty
+/// class.
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    if denominator == 0 {
+        None
+    } else {
+        Some(numerator as f64 / denominator as f64)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Confusion {
+    pub tp: usize,
+    pub fp: usize,
+    pub tn: usize,
+    #[serde(rename = "fn")]
+    pub fn_: usize,
+}
+
+impl Confusion {
+    fn add(&mut self, expected: u8, predicted: u8) {
+        match (expected != 0, predicted != 0) {
+            (false, false) => self.tn += 1,
+            (false, true) => self.fp += 1,
+            (true, false) => self.fn_ += 1,
+            (true, true) if expected == predicted => self.tp += 1,
+            (true, true) => self.fp += 1,
+        }
+    }
+
+    pub fn sensitivity(&self) -> Option<f64> {
+        ratio(self.tp, self.tp + self.fn_)
+    }
+
+    pub fn specificity(&self) -> Option<f64> {
+        ratio(self.tn, self.tn + self.fp)
+    }
+
+    pub fn precision(&self) -> Option<f64> {
+        ratio(self.tp, self.tp + self.fp)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GeneCounts {
+    pub annotated: usize,
+    pub predicted: usize,
+    /// Predicted genes with the same strand and stop codon as an annotated gene.
+    pub correct_stops: usize,
+    /// Correctly predicted stops for which the start codon is correct too.
+    pub correct_starts: usize,
+}
+
+impl GeneCounts {
+    pub fn sensitivity(&self) -> Option<f64> {
+        ratio(self.correct_stops, self.annotated)
+    }
+
+    /// Without true negatives, specificity is expressed as the fraction of
+    /// predicted genes that are correct.
+    pub fn specificity(&self) -> Option<f64> {
+        ratio(self.correct_stops, self.predicted)
+    }
+
+    pub fn start_accuracy(&self) -> Option<f64> {
+        ratio(self.correct_starts, self.correct_stops)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub nucleotide: Confusion,
+    pub nucleotide_forward: Confusion,
+    pub nucleotide_reverse: Confusion,
+    pub codon_frame: Confusion,
+    pub genes: GeneCounts,
+    pub genes_forward: GeneCounts,
+    pub genes_reverse: GeneCounts,
+}
+
+const FORWARD: u8 = 0b000111;
+const REVERSE: u8 = 0b111000;
+
+fn strands(frames: u8) -> u8 {
+    (if frames & FORWARD != 0 { 1 } else { 0 }) | (if frames & REVERSE != 0 { 2 } else { 0 })
+}
+
+/// Compares predictions to the expected genes. Sequences without a known
+/// length are assumed to end at the last annotated or predicted position.
+pub fn evaluate(
+    expected: &[Annotation],
+    predicted: &[Annotation],
+    lengths: &HashMap<String, usize>,
+) -> Evaluation {
+    let mut evaluation = Evaluation::default();
+
+    let mut by_seqid: HashMap<&str, (Vec<&Annotation>, Vec<&Annotation>)> = HashMap::new();
+    for seqid in lengths.keys() {
+        by_seqid.entry(seqid).or_default();
+    }
+    for gene in expected {
+        by_seqid.entry(&gene.seqid).or_default().0.push(gene);
+    }
+    for gene in predicted {
+        by_seqid.entry(&gene.seqid).or_default().1.push(gene);
+    }
+
+    for (seqid, (expected, predicted)) in by_seqid {
+        let length = lengths.get(seqid).copied().unwrap_or_else(|| {
+            expected
+                .iter()
+                .chain(predicted.iter())
+                .map(|gene| gene.end)
+                .max()
+                .unwrap_or(0)
+        });
+
+        let mut expected_frames = vec![0u8; length];
+        let mut predicted_frames = vec![0u8; length];
+        for (genes, frames) in [
+            (&expected, &mut expected_frames),
+            (&predicted, &mut predicted_frames),
+        ] {
+            for gene in genes.iter() {
+                let end = gene.end.min(length);
+                for frame in frames.iter_mut().take(end).skip(gene.start.max(1) - 1) {
+                    *frame |= gene.frame_bit();
+                }
+            }
+        }
+
+        for (&e, &p) in expected_frames.iter().zip(predicted_frames.iter()) {
+            evaluation.nucleotide.add(strands(e), strands(p));
+            evaluation.nucleotide_forward.add(e & FORWARD, p & FORWARD);
+            evaluation.nucleotide_reverse.add(e & REVERSE, p & REVERSE);
+            evaluation.codon_frame.add(e, p);
+        }
+
+        for gene in &expected {
+            let counts = if gene.forward_strand {
+                &mut evaluation.genes_forward
+            } else {
+                &mut evaluation.genes_reverse
+            };
+            counts.annotated += 1;
+        }
+        for gene in &predicted {
+            let counts = if gene.forward_strand {
+                &mut evaluation.genes_forward
+            } else {
+                &mut evaluation.genes_reverse
+            };
+            counts.predicted += 1;
+            let matches: Vec<&&Annotation> = expected
+                .iter()
+                .filter(|e| e.forward_strand == gene.forward_strand && e.stop() == gene.stop())
+                .collect();
+            if !matches.is_empty() {
+                counts.correct_stops += 1;
+                if matches
+                    .iter()
+                    .any(|e| e.start_codon() == gene.start_codon())
+                {
+                    counts.correct_starts += 1;
+                }
+            }
+        }
+    }
+
+    for counts in [evaluation.genes_forward, evaluation.genes_reverse] {
+        evaluation.genes.annotated += counts.annotated;
+        evaluation.genes.predicted += counts.predicted;
+        evaluation.genes.correct_stops += counts.correct_stops;
+        evaluation.genes.correct_starts += counts.correct_starts;
+    }
+
+    evaluation
+}
+
+/// A rate as a percentage, or `-` if it is undefined.
+fn percentage(rate: Option<f64>) -> String {
+    rate.map_or("-".to_string(), |rate| format!("{:.2}", rate * 100.0))
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16}{:>10}{:>10}{:>10}{:>10}{:>8}{:>8}{:>8}",
+            "level", "TP", "FP", "TN", "FN", "sens", "spec", "prec"
+        )?;
+        for (name, c) in [
+            ("nucleotide", &self.nucleotide),
+            ("nucleotide (+)", &self.nucleotide_forward),
+            ("nucleotide (-)", &self.nucleotide_reverse),
+            ("codon-frame", &self.codon_frame),
+        ] {
+            writeln!(
+                f,
+                "{:<16}{:>10}{:>10}{:>10}{:>10}{:>8}{:>8}{:>8}",
+                name,
+                c.tp,
+                c.fp,
+                c.tn,
+                c.fn_,
+                percentage(c.sensitivity()),
+                percentage(c.specificity()),
+                percentage(c.precision())
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<16}{:>10}{:>10}{:>10}{:>10}{:>8}{:>8}{:>8}",
+            "level", "expected", "predicted", "stops", "starts", "sens", "spec", "start"
+        )?;
+        for (name, c) in [
+            ("gene", &self.genes),
+            ("gene (+)", &self.genes_forward),
+            ("gene (-)", &self.genes_reverse),
+        ] {
+            writeln!(
+                f,
+                "{:<16}{:>10}{:>10}{:>10}{:>10}{:>8}{:>8}{:>8}",
+                name,
+                c.annotated,
+                c.predicted,
+                c.correct_stops,
+                c.correct_starts,
+                percentage(c.sensitivity()),
+                percentage(c.specificity()),
+                percentage(c.start_accuracy())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gene(start: usize, end: usize, forward_strand: bool) -> Annotation {
+        Annotation {
+            seqid: String::from("seq"),
+            start,
+            end,
+            forward_strand,
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut lengths = HashMap::new();
+        lengths.insert(String::from("seq"), 100);
+        let expected = vec![gene(1, 30, true), gene(61, 90, false)];
+        let predicted = vec![gene(4, 30, true), gene(61, 93, true)];
+        let evaluation = evaluate(&expected, &predicted, &lengths);
+
+        assert_eq!(
+            Confusion {
+                tp: 27,
+                fp: 33,
+                tn: 37,
+                fn_: 3
+            },
+            evaluation.nucleotide
+        );
+        assert_eq!(
+            Confusion {
+                tp: 0,
+                fp: 0,
+                tn: 70,
+                fn_: 30
+            },
+            evaluation.nucleotide_reverse
+        );
+        assert_eq!(27, evaluation.codon_frame.tp);
+        assert_eq!(
+            GeneCounts {
+                annotated: 2,
+                predicted: 2,
+                correct_stops: 1,
+                correct_starts: 0
+            },
+            evaluation.genes
+        );
+        assert_eq!(Some(0.9), evaluation.nucleotide.sensitivity());
+        assert_eq!(None, evaluation.nucleotide_reverse.precision());
+        assert_eq!(Some(0.0), evaluation.genes.start_accuracy());
+    }
+
+    #[test]
+    fn test_empty_classes() {
+        let evaluation = evaluate(&[], &[], &HashMap::new());
+        assert_eq!(None, evaluation.genes.sensitivity());
+        assert_eq!(None, evaluation.codon_frame.specificity());
+        let display = evaluation.to_string();
+        assert!(!display.contains("NaN"), "{}", display);
+        assert!(display.contains("       -"), "{}", display);
+    }
+
+    #[test]
+    fn test_read_predictions() {
+        let meta = "# processor FragGeneScanRs 1.1.0\n# training-file complete\n>seq\n4\t30\t+\t1\t1.3\tI:\tD:\n";
+        let gff = "##gff-version 3\n#!processor FragGeneScanRs 1.1.0\nseq\tFGS\tCDS\t4\t30\t.\t+\t0\tID=seq_4_30_+\n";
+        for predictions in [meta, gff] {
+            let annotations = read_predictions(predictions.as_bytes()).unwrap();
+            assert_eq!(vec![gene(4, 30, true)], annotations.genes);
+        }
+    }
+
+    #[test]
+    fn test_read_gff() {
+        let gff = "##gff-version 3\n##sequence-region seq 1 100\nseq\tFGS\tCDS\t4\t30\t.\t+\t0\tID=seq_4_30_+\nseq\tFGS\tgene\t4\t30\t.\t+\t.\tID=g\n";
+        let annotations = read_gff(gff.as_bytes(), "CDS").unwrap();
+        assert_eq!(vec![gene(4, 30, true)], annotations.genes);
+        assert_eq!(Some(&100), annotations.lengths.get("seq"));
+    }
+}
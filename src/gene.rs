@@ -2,19 +2,32 @@ extern crate thiserror;
 use thiserror::Error;
 
 use crate::dna::Nuc::{A, C, G, T};
-use crate::dna::{trinucleotide, Nuc, ANTI_CODON_CODE, CODON_CODE};
+use crate::dna::{translate, trinucleotide, Nuc, ANTI_CODON_CODE, CODON_CODE};
+use crate::peptide::Digestion;
 
 #[derive(Debug, PartialEq)]
 pub struct ReadPrediction {
     pub head: Vec<u8>,
+    /// Rest of the header line after the identifier in `head`, written
+    /// after the gene identifiers in the protein and DNA output.
+    pub description: Vec<u8>,
     pub genes: Vec<Gene>,
+    /// Positions where the traceback ended in a None-state, which were
+    /// replaced by the non-coding state.
+    pub none_states: usize,
+    /// GC content bin of the model the read was decoded with, as returned
+    /// by `count_cg_content`, or `None` if it was not decoded.
+    pub cg: Option<usize>,
 }
 
 impl ReadPrediction {
     pub fn new(head: Vec<u8>) -> Self {
         ReadPrediction {
-            head: head,
+            head,
+            description: vec![],
             genes: vec![],
+            none_states: 0,
+            cg: None,
         }
     }
 
@@ -25,54 +38,173 @@ impl ReadPrediction {
         dnabuf: &mut Option<Vec<u8>>,
         formatted: bool,
         whole_genome: bool,
+        ids: &GeneIds,
     ) -> Result<(), GeneError> {
         if let Some(metabuf) = metabuf {
-            self.meta(&mut *metabuf)?;
+            self.meta(&mut *metabuf, ids)?;
         }
         if let Some(dnabuf) = dnabuf {
-            self.dna(&mut *dnabuf, formatted)?;
+            self.dna(&mut *dnabuf, formatted, ids)?;
         }
         if let Some(aabuf) = aabuf {
-            self.protein(&mut *aabuf, whole_genome)?;
+            self.protein(&mut *aabuf, whole_genome, ids)?;
         }
         Ok(())
     }
 
-    pub fn meta(&self, buf: &mut Vec<u8>) -> Result<(), GeneError> {
+    /// The identifiers of the genes, with the description if any.
+    fn gene_ids<'a>(
+        &'a self,
+        ids: &'a GeneIds,
+    ) -> Result<impl Iterator<Item = (&'a Gene, String)> + 'a, GeneError> {
+        let head = std::str::from_utf8(&self.head)?;
+        let description = std::str::from_utf8(&self.description)?;
+        Ok(self.genes.iter().enumerate().map(move |(i, gene)| {
+            let id = ids.format(head, i + 1, gene);
+            if description.is_empty() {
+                (gene, id)
+            } else {
+                (gene, format!("{} {}", id, description))
+            }
+        }))
+    }
+
+    pub fn meta(&self, buf: &mut Vec<u8>, ids: &GeneIds) -> Result<(), GeneError> {
         if !self.genes.is_empty() {
-            buf.append(&mut format!(">{}\n", std::str::from_utf8(&self.head)?).into_bytes())
-        }
-        for gene in &self.genes {
-            gene.meta(buf);
+            let head = std::str::from_utf8(&self.head)?;
+            buf.append(&mut format!(">{}\n", head).into_bytes());
+            for (i, gene) in self.genes.iter().enumerate() {
+                let id = ids.custom().then(|| ids.format(head, i + 1, gene));
+                gene.meta(buf, id.as_deref());
+            }
         }
         Ok(())
     }
 
-    pub fn gff(&self, buf: &mut Vec<u8>) -> Result<(), GeneError> {
+    pub fn gff(&self, buf: &mut Vec<u8>, ids: &GeneIds) -> Result<(), GeneError> {
         if !self.genes.is_empty() {
             let head = std::str::from_utf8(&self.head)?;
-            for gene in &self.genes {
-                gene.gff(buf, &head);
+            for (i, gene) in self.genes.iter().enumerate() {
+                gene.gff(buf, head, &ids.format(head, i + 1, gene));
             }
         }
         Ok(())
     }
 
-    pub fn dna(&self, buf: &mut Vec<u8>, formatted: bool) -> Result<(), GeneError> {
-        for gene in &self.genes {
-            gene.dna(buf, &self.head, formatted)?;
+    pub fn dna(&self, buf: &mut Vec<u8>, formatted: bool, ids: &GeneIds) -> Result<(), GeneError> {
+        for (gene, id) in self.gene_ids(ids)? {
+            gene.dna(buf, &id, formatted)?;
         }
         Ok(())
     }
 
-    pub fn protein(&self, buf: &mut Vec<u8>, whole_genome: bool) -> Result<(), GeneError> {
-        for gene in &self.genes {
-            gene.protein(buf, &self.head, whole_genome)?;
+    pub fn protein(
+        &self,
+        buf: &mut Vec<u8>,
+        whole_genome: bool,
+        ids: &GeneIds,
+    ) -> Result<(), GeneError> {
+        for (gene, id) in self.gene_ids(ids)? {
+            gene.protein(buf, &id, whole_genome)?;
+        }
+        Ok(())
+    }
+
+    pub fn peptides(
+        &self,
+        buf: &mut Vec<u8>,
+        whole_genome: bool,
+        digestion: &Digestion,
+    ) -> Result<(), GeneError> {
+        if !self.genes.is_empty() {
+            let head = std::str::from_utf8(&self.head)?;
+            for gene in &self.genes {
+                gene.peptides(buf, head, whole_genome, digestion)?;
+            }
         }
         Ok(())
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum IdPart {
+    Literal(String),
+    Read,
+    Number,
+    Start,
+    End,
+    Strand,
+    Frame,
+}
+
+/// Template for the identifiers of predicted genes, in which `{read}`,
+/// `{n}` (the number of the gene within its read, from 1), `{start}`,
+/// `{end}`, `{strand}` and `{frame}` are replaced by their values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneIds {
+    parts: Vec<IdPart>,
+}
+
+impl GeneIds {
+    /// The identifiers used by FragGeneScan.
+    pub const DEFAULT: &'static str = "{read}_{start}_{end}_{strand}";
+
+    pub fn parse(template: &str) -> Result<Self, GeneError> {
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| GeneError::Template(template.to_owned()))?;
+            if open > 0 {
+                parts.push(IdPart::Literal(rest[..open].to_owned()));
+            }
+            parts.push(match &rest[open + 1..open + close] {
+                "read" => IdPart::Read,
+                "n" => IdPart::Number,
+                "start" => IdPart::Start,
+                "end" => IdPart::End,
+                "strand" => IdPart::Strand,
+                "frame" => IdPart::Frame,
+                _ => return Err(GeneError::Template(template.to_owned())),
+            });
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(IdPart::Literal(rest.to_owned()));
+        }
+        Ok(GeneIds { parts })
+    }
+
+    /// Whether this differs from the FragGeneScan identifiers.
+    pub fn custom(&self) -> bool {
+        *self != GeneIds::default()
+    }
+
+    /// The identifier of the `n`th gene of read `head`.
+    pub fn format(&self, head: &str, n: usize, gene: &Gene) -> String {
+        let mut id = String::new();
+        for part in &self.parts {
+            match part {
+                IdPart::Literal(literal) => id.push_str(literal),
+                IdPart::Read => id.push_str(head),
+                IdPart::Number => id.push_str(&n.to_string()),
+                IdPart::Start => id.push_str(&gene.start.to_string()),
+                IdPart::End => id.push_str(&gene.end.to_string()),
+                IdPart::Strand => id.push(if gene.forward_strand { '+' } else { '-' }),
+                IdPart::Frame => id.push_str(&gene.frame.to_string()),
+            }
+        }
+        id
+    }
+}
+
+impl Default for GeneIds {
+    fn default() -> Self {
+        GeneIds::parse(GeneIds::DEFAULT).unwrap()
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Gene {
     pub start: usize,
@@ -86,10 +218,10 @@ pub struct Gene {
 }
 
 impl Gene {
-    pub fn meta(&self, buf: &mut Vec<u8>) {
+    pub fn meta(&self, buf: &mut Vec<u8>, id: Option<&str>) {
         buf.append(
             &mut format!(
-                "{}\t{}\t{}\t{}\t{:.6}\tI:{}\tD:{}\n",
+                "{}\t{}\t{}\t{}\t{:.6}\tI:{}\tD:{}{}\n",
                 self.start,
                 self.end,
                 if self.forward_strand { '+' } else { '-' },
@@ -102,31 +234,29 @@ impl Gene {
                 self.deleted
                     .iter()
                     .map(|i: &usize| { format!("{},", i) })
-                    .collect::<String>()
+                    .collect::<String>(),
+                id.map(|id| format!("\tID:{}", id)).unwrap_or_default()
             )
             .into_bytes(),
         );
     }
 
-    pub fn gff(&self, buf: &mut Vec<u8>, head: &str) {
+    pub fn gff(&self, buf: &mut Vec<u8>, head: &str, id: &str) {
         buf.append(
             &mut format!(
-                "{}\tFGS\tCDS\t{}\t{}\t.\t{}\t{}\tID={}_{}_{}_{};product=predicted protein\n",
+                "{}\tFGS\tCDS\t{}\t{}\t.\t{}\t{}\tID={};product=predicted protein\n",
                 head,
                 self.start,
                 self.end,
                 if self.forward_strand { '+' } else { '-' },
                 self.frame - 1,
-                head,
-                self.start,
-                self.end,
-                if self.forward_strand { '+' } else { '-' }
+                id,
             )
             .into_bytes(),
         );
     }
 
-    pub fn dna(&self, buf: &mut Vec<u8>, head: &Vec<u8>, formatted: bool) -> Result<(), GeneError> {
+    pub fn dna(&self, buf: &mut Vec<u8>, id: &str, formatted: bool) -> Result<(), GeneError> {
         let dna: Vec<u8> = match (self.forward_strand, formatted) {
             (true, true) => self.dna.iter().map(|&n| u8::from(n)).collect(),
             (true, false) => self
@@ -145,40 +275,25 @@ impl Gene {
                 .collect(),
         };
 
-        buf.append(
-            &mut format!(
-                ">{}_{}_{}_{}\n{}\n",
-                std::str::from_utf8(head)?,
-                self.start,
-                self.end,
-                if self.forward_strand { '+' } else { '-' },
-                std::str::from_utf8(&dna)?,
-            )
-            .into_bytes(),
-        );
+        buf.append(&mut format!(">{}\n{}\n", id, std::str::from_utf8(&dna)?).into_bytes());
 
         Ok(())
     }
 
-    pub fn protein(
-        &self,
-        buf: &mut Vec<u8>,
-        head: &Vec<u8>,
-        whole_genome: bool,
-    ) -> Result<(), GeneError> {
+    pub fn translate(&self, whole_genome: bool) -> Vec<u8> {
         let dna = self
             .dna
             .iter()
             .filter(|n| !n.is_insertion())
-            .map(|&n| n)
+            .copied()
             .collect::<Vec<Nuc>>();
         let mut protein: Vec<u8> = if self.forward_strand {
             dna.chunks_exact(3)
-                .map(|c| trinucleotide(c).map(|i| CODON_CODE[i]).unwrap_or(b'X'))
+                .map(|c| translate(c, &CODON_CODE))
                 .collect()
         } else {
             dna.rchunks_exact(3)
-                .map(|c| trinucleotide(c).map(|i| ANTI_CODON_CODE[i]).unwrap_or(b'X'))
+                .map(|c| translate(c, &ANTI_CODON_CODE))
                 .collect()
         };
         if protein.last() == Some(&b'*') {
@@ -202,23 +317,131 @@ impl Gene {
             }
         }
 
-        buf.append(
-            &mut format!(
-                ">{}_{}_{}_{}\n{}\n",
-                std::str::from_utf8(head)?,
-                self.start,
-                self.end,
-                if self.forward_strand { '+' } else { '-' },
-                std::str::from_utf8(&protein)?,
-            )
-            .into_bytes(),
-        );
+        protein
+    }
+
+    pub fn protein(
+        &self,
+        buf: &mut Vec<u8>,
+        id: &str,
+        whole_genome: bool,
+    ) -> Result<(), GeneError> {
+        let protein = self.translate(whole_genome);
+        buf.append(&mut format!(">{}\n{}\n", id, std::str::from_utf8(&protein)?).into_bytes());
+        Ok(())
+    }
+
+    pub fn peptides(
+        &self,
+        buf: &mut Vec<u8>,
+        head: &str,
+        whole_genome: bool,
+        digestion: &Digestion,
+    ) -> Result<(), GeneError> {
+        let protein = self.translate(whole_genome);
+        for (start, end) in digestion.digest(&protein) {
+            buf.append(
+                &mut format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    head,
+                    self.start,
+                    self.end,
+                    if self.forward_strand { '+' } else { '-' },
+                    start + 1,
+                    end,
+                    std::str::from_utf8(&protein[start..end])?,
+                )
+                .into_bytes(),
+            );
+        }
         Ok(())
     }
 }
 
+/// Criteria a predicted gene has to meet in order to be reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneFilter {
+    /// Minimum number of nucleotides in the gene, including predicted insertions and deletions.
+    /// Genes that span fewer than 6 nucleotides are never reported, as their
+    /// score leaves out the first and last codon. For shorter genes than
+    /// FragGeneScan reports, the start codon of complete genomic sequences is
+    /// not refined when the window of 30 nucleotides around it does not fit
+    /// in the sequence.
+    pub min_length: usize,
+    /// Minimum number of amino acids in the translated protein, excluding the stop codon.
+    pub min_protein_length: usize,
+    /// Minimum score of the prediction.
+    pub min_score: f64,
+    /// Maximum number of inserted and deleted nucleotides in the gene.
+    pub max_indels: usize,
+}
+
+impl GeneFilter {
+    /// The filter used by FragGeneScan: genes longer than 60 nucleotides
+    /// (120 for complete genomic sequences), regardless of their score.
+    pub fn new(whole_genome: bool) -> Self {
+        GeneFilter {
+            min_length: if whole_genome { 121 } else { 61 },
+            min_protein_length: 0,
+            min_score: f64::NEG_INFINITY,
+            max_indels: usize::MAX,
+        }
+    }
+
+    pub fn accepts(&self, gene: &Gene, whole_genome: bool) -> bool {
+        gene.score >= self.min_score
+            && gene.inserted.len() + gene.deleted.len() <= self.max_indels
+            && (self.min_protein_length == 0
+                || gene.translate(whole_genome).len() >= self.min_protein_length)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum GeneError {
     #[error("could not convert header back to UTF-8")]
     Utf8Error(#[from] std::str::Utf8Error),
+    #[error("invalid gene identifier template '{0}'")]
+    Template(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna::dna;
+
+    #[test]
+    fn test_gene_ids() {
+        let mut prediction = ReadPrediction::new(b"read1".to_vec());
+        prediction.description = b"sample=A length=30".to_vec();
+        prediction.genes.push(Gene {
+            start: 4,
+            end: 15,
+            frame: 1,
+            score: 1.0,
+            dna: dna("ATGAAACCCTAA"),
+            forward_strand: false,
+            inserted: vec![],
+            deleted: vec![],
+        });
+
+        let mut buf = vec![];
+        let ids = GeneIds::default();
+        assert!(!ids.custom());
+        prediction.protein(&mut buf, false, &ids).unwrap();
+        prediction.gff(&mut buf, &ids).unwrap();
+        let ids = GeneIds::parse("{read}|{n}|{start}-{end}|{strand}|frame={frame}").unwrap();
+        assert!(ids.custom());
+        prediction.protein(&mut buf, false, &ids).unwrap();
+        prediction.meta(&mut buf, &ids).unwrap();
+        assert_eq!(
+            ">read1_4_15_- sample=A length=30\nLGFH\n\
+             read1\tFGS\tCDS\t4\t15\t.\t-\t0\tID=read1_4_15_-;product=predicted protein\n\
+             >read1|1|4-15|-|frame=1 sample=A length=30\nLGFH\n\
+             >read1\n4\t15\t-\t1\t1.000000\tI:\tD:\tID:read1|1|4-15|-|frame=1\n",
+            String::from_utf8(buf).unwrap()
+        );
+
+        assert!(GeneIds::parse("{read}_{stop}").is_err());
+        assert!(GeneIds::parse("{read").is_err());
+    }
 }
@@ -1,17 +1,21 @@
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::ErrorKind;
 use std::io::{self, BufRead, Lines};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+extern crate sha2;
+use sha2::{Digest, Sha256};
+
 extern crate thiserror;
 use thiserror::Error;
 
 extern crate strum;
 use strum::EnumCount;
-use strum_macros::{EnumCount, EnumIter};
+use strum_macros::{EnumCount, EnumIter, EnumString, EnumVariantNames};
 
+use crate::config::ModelConfig;
 use crate::dna::{ACGT, BI_ACGT, CG_MAX, CG_MIN, TRI_ACGT};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, EnumCount, EnumIter)]
@@ -81,15 +85,86 @@ pub struct Transition {
     pub es1: f64,
 }
 
+/// Insertion and deletion transitions, as used within homopolymer runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Indel {
+    pub mi: f64,
+    pub ii: f64,
+    pub md: f64,
+    pub dd: f64,
+}
+
 pub const PERIOD: usize = 6;
 const WINDOW: usize = 61;
 
+/// Longest run of deleted nucleotides the model considers within a gene.
+pub const MAX_DELETION_LENGTH: usize = 5;
+
 #[derive(Default)]
 pub struct Global {
     pub pi: [f64; State::COUNT],
     pub tr: Transition,
     pub tr_ii: [[f64; ACGT]; ACGT],
     pub tr_mi: [[f64; ACGT]; ACGT],
+    /// Transitions replacing `MI`, `II`, `MD` and `DD` after a homopolymer
+    /// run of at least the given length, ordered by length.
+    pub tr_homopolymer: Vec<(usize, Indel)>,
+    pub max_deletion_length: usize,
+    /// Log probability of each nucleotide skipped by a deletion.
+    pub deleted_nucleotide: f64,
+}
+
+impl Global {
+    /// The insertion and deletion transitions following a homopolymer run
+    /// of `run` nucleotides.
+    pub fn indel(&self, run: usize) -> Indel {
+        self.tr_homopolymer
+            .iter()
+            .rev()
+            .find(|(length, _)| run >= *length)
+            .map(|(_, indel)| *indel)
+            .unwrap_or(Indel {
+                mi: self.tr.mi,
+                ii: self.tr.ii,
+                md: self.tr.md,
+                dd: self.tr.dd,
+            })
+    }
+
+    /// Replaces the transitions and deletion limits of the model by those
+    /// set in the configuration. An overridden `MI`, `II`, `MD` or `DD`
+    /// transition also replaces the one of the homopolymer runs.
+    pub fn apply_overrides(&mut self, model: &ModelConfig) -> Result<(), OverrideError> {
+        let ln = |probability: Option<f64>| match probability {
+            // a zero probability would make some reads impossible to decode
+            Some(p) if !(p > 0.0 && p <= 1.0) => Err(OverrideError::NotAProbability(p)),
+            p => Ok(p.map(f64::ln)),
+        };
+        if let Some(mi) = ln(model.match_insertion)? {
+            self.tr.mi = mi;
+            self.tr_homopolymer.iter_mut().for_each(|(_, i)| i.mi = mi);
+        }
+        if let Some(md) = ln(model.match_deletion)? {
+            self.tr.md = md;
+            self.tr_homopolymer.iter_mut().for_each(|(_, i)| i.md = md);
+        }
+        if let Some(ii) = ln(model.insertion_insertion)? {
+            self.tr.ii = ii;
+            self.tr_homopolymer.iter_mut().for_each(|(_, i)| i.ii = ii);
+        }
+        if let Some(dd) = ln(model.deletion_deletion)? {
+            self.tr.dd = dd;
+            self.tr_homopolymer.iter_mut().for_each(|(_, i)| i.dd = dd);
+        }
+        if let Some(deleted) = ln(model.deleted_nucleotide)? {
+            self.deleted_nucleotide = deleted;
+        }
+        if model.max_deletion_length > MAX_DELETION_LENGTH {
+            return Err(OverrideError::DeletionTooLong(model.max_deletion_length));
+        }
+        self.max_deletion_length = model.max_deletion_length;
+        Ok(())
+    }
 }
 
 pub struct Local {
@@ -117,15 +192,76 @@ pub enum TrainingDataError {
     UnknownTransitionState,
     #[error("could not read training file {0}")]
     Io(PathBuf, #[source] io::Error),
+    #[error("missing value in line '{2}' in section {1} of {0}")]
+    MissingValue(PathBuf, String, String),
     #[error("malformed value '{2}' in section {1} of {0}")]
     MalformedValue(PathBuf, String, String, #[source] std::num::ParseFloatError),
+    #[error("malformed homopolymer length '{2}' in section {1} of {0}")]
+    MalformedLength(PathBuf, String, String, #[source] std::num::ParseIntError),
+}
+
+/// A value in a parsed model that cannot have come from a sensible
+/// training file.
+#[derive(Error, Debug, PartialEq)]
+pub enum ModelProblem {
+    #[error("{0} is {1}, which is not a probability")]
+    NotAProbability(String, f64),
+    #[error("{0} sum to {1} instead of 1")]
+    NotNormalized(String, f64),
+}
+
+/// An override in the configuration that cannot be applied to the model.
+#[derive(Error, Debug, PartialEq)]
+pub enum OverrideError {
+    #[error("{0} is not a probability greater than 0")]
+    NotAProbability(f64),
+    #[error(
+        "the maximum deletion length is {0}, but should be at most {}",
+        MAX_DELETION_LENGTH
+    )]
+    DeletionTooLong(usize),
+}
+
+/// The training files bundled with the executable.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, EnumVariantNames)]
+pub enum Model {
+    /// Complete genomic sequences or short sequence reads without sequencing error.
+    #[strum(serialize = "complete")]
+    Complete,
+    /// Sanger sequencing reads with about 0.5% error rate.
+    #[strum(serialize = "sanger_5")]
+    Sanger5,
+    /// Sanger sequencing reads with about 1% error rate.
+    #[strum(serialize = "sanger_10")]
+    Sanger10,
+    /// 454 pyrosequencing reads with about 0.5% error rate.
+    #[strum(serialize = "454_5")]
+    Pyro5,
+    /// 454 pyrosequencing reads with about 1% error rate.
+    #[strum(serialize = "454_10")]
+    Pyro10,
+    /// 454 pyrosequencing reads with about 3% error rate.
+    #[strum(serialize = "454_30")]
+    Pyro30,
+    /// Illumina sequencing reads with about 0.1% error rate.
+    #[strum(serialize = "illumina_1")]
+    Illumina1,
+    /// Illumina sequencing reads with about 0.5% error rate.
+    #[strum(serialize = "illumina_5")]
+    Illumina5,
+    /// Illumina sequencing reads with about 1% error rate.
+    #[strum(serialize = "illumina_10")]
+    Illumina10,
 }
 
+#[allow(clippy::box_default)]
 pub fn get_train_from_file(
     train_dir: PathBuf,
     filename: PathBuf,
 ) -> Result<(Box<Global>, Vec<Local>), TrainingDataError> {
     let mut global: Box<Global> = Box::new(Default::default());
+    global.max_deletion_length = MAX_DELETION_LENGTH;
+    global.deleted_nucleotide = 0.25_f64.ln();
     let mut locals: Vec<Local> = (0..CG_MAX - CG_MIN)
         .map(|_| Local {
             e_m: [[[0.0; ACGT]; BI_ACGT]; PERIOD],
@@ -156,43 +292,94 @@ pub fn get_train_from_file(
     Ok((global, locals))
 }
 
+/// The lines of a transition training file, as read by
+/// [`get_train_from_file`].
+pub fn transition_lines(
+    train_dir: PathBuf,
+    filename: PathBuf,
+) -> Result<Vec<String>, TrainingDataError> {
+    let filename = train_dir.join(filename);
+    lines_from_file(&filename)?
+        .map(|line| line.map_err(|e| TrainingDataError::Io(filename.to_owned(), e)))
+        .collect()
+}
+
 fn lines_from_bytes(bytes: &'static [u8]) -> Lines<Box<dyn BufRead>> {
     let b: Box<dyn BufRead> = Box::new(bytes);
     b.lines()
 }
 
+/// The bundled training file with this name, if any.
+fn bundled(filename: &Path) -> Option<&'static [u8]> {
+    match filename.file_name().and_then(OsStr::to_str) {
+        Some("454_10") => Some(include_bytes!("../train/454_10")),
+        Some("454_30") => Some(include_bytes!("../train/454_30")),
+        Some("454_5") => Some(include_bytes!("../train/454_5")),
+        Some("complete") => Some(include_bytes!("../train/complete")),
+        Some("gene") => Some(include_bytes!("../train/gene")),
+        Some("illumina_1") => Some(include_bytes!("../train/illumina_1")),
+        Some("illumina_10") => Some(include_bytes!("../train/illumina_10")),
+        Some("illumina_5") => Some(include_bytes!("../train/illumina_5")),
+        Some("noncoding") => Some(include_bytes!("../train/noncoding")),
+        Some("pwm") => Some(include_bytes!("../train/pwm")),
+        Some("rgene") => Some(include_bytes!("../train/rgene")),
+        Some("sanger_10") => Some(include_bytes!("../train/sanger_10")),
+        Some("sanger_5") => Some(include_bytes!("../train/sanger_5")),
+        Some("start") => Some(include_bytes!("../train/start")),
+        Some("start1") => Some(include_bytes!("../train/start1")),
+        Some("stop") => Some(include_bytes!("../train/stop")),
+        Some("stop1") => Some(include_bytes!("../train/stop1")),
+        _ => None,
+    }
+}
+
 fn lines_from_file(filename: &PathBuf) -> Result<Lines<Box<dyn BufRead>>, TrainingDataError> {
     match File::open(filename) {
         Ok(file) => {
             let b: Box<dyn BufRead> = Box::new(io::BufReader::new(file));
             Ok(b.lines())
         }
-        Err(e) if e.kind() == ErrorKind::NotFound => {
-            match filename.file_name().and_then(OsStr::to_str) {
-                Some("454_10") => Ok(lines_from_bytes(include_bytes!("../train/454_10"))),
-                Some("454_30") => Ok(lines_from_bytes(include_bytes!("../train/454_30"))),
-                Some("454_5") => Ok(lines_from_bytes(include_bytes!("../train/454_5"))),
-                Some("complete") => Ok(lines_from_bytes(include_bytes!("../train/complete"))),
-                Some("gene") => Ok(lines_from_bytes(include_bytes!("../train/gene"))),
-                Some("illumina_1") => Ok(lines_from_bytes(include_bytes!("../train/illumina_1"))),
-                Some("illumina_10") => Ok(lines_from_bytes(include_bytes!("../train/illumina_10"))),
-                Some("illumina_5") => Ok(lines_from_bytes(include_bytes!("../train/illumina_5"))),
-                Some("noncoding") => Ok(lines_from_bytes(include_bytes!("../train/noncoding"))),
-                Some("pwm") => Ok(lines_from_bytes(include_bytes!("../train/pwm"))),
-                Some("rgene") => Ok(lines_from_bytes(include_bytes!("../train/rgene"))),
-                Some("sanger_10") => Ok(lines_from_bytes(include_bytes!("../train/sanger_10"))),
-                Some("sanger_5") => Ok(lines_from_bytes(include_bytes!("../train/sanger_5"))),
-                Some("start") => Ok(lines_from_bytes(include_bytes!("../train/start"))),
-                Some("start1") => Ok(lines_from_bytes(include_bytes!("../train/start1"))),
-                Some("stop") => Ok(lines_from_bytes(include_bytes!("../train/stop"))),
-                Some("stop1") => Ok(lines_from_bytes(include_bytes!("../train/stop1"))),
-                _ => Err(TrainingDataError::Io(filename.to_owned(), e)),
-            }
-        }
+        Err(e) if e.kind() == ErrorKind::NotFound => match bundled(filename) {
+            Some(bytes) => Ok(lines_from_bytes(bytes)),
+            None => Err(TrainingDataError::Io(filename.to_owned(), e)),
+        },
         Err(e) => Err(TrainingDataError::Io(filename.to_owned(), e)),
     }
 }
 
+/// SHA-256 checksum (in hexadecimal) of the training file followed by the
+/// emission files, as read by [`get_train_from_file`].
+pub fn checksum(train_dir: PathBuf, filename: PathBuf) -> Result<String, TrainingDataError> {
+    let emissions = [
+        "gene",
+        "rgene",
+        "noncoding",
+        "start",
+        "stop",
+        "stop1",
+        "start1",
+        "pwm",
+    ];
+    let mut hasher = Sha256::new();
+    for name in std::iter::once(filename).chain(emissions.iter().map(PathBuf::from)) {
+        let filename = train_dir.join(name);
+        match fs::read(&filename) {
+            Ok(bytes) => hasher.update(bytes),
+            Err(e) if e.kind() == ErrorKind::NotFound => match bundled(&filename) {
+                Some(bytes) => hasher.update(bytes),
+                None => return Err(TrainingDataError::Io(filename, e)),
+            },
+            Err(e) => return Err(TrainingDataError::Io(filename, e)),
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[allow(clippy::ptr_arg)]
 fn next_line<R: BufRead>(
     filename: &PathBuf,
     lines: &mut Lines<R>,
@@ -203,12 +390,17 @@ fn next_line<R: BufRead>(
         .map_err(|e| TrainingDataError::Io(filename.to_path_buf(), e))
 }
 
+/// Parses the value in a column of a line split on whitespace.
 fn parse_float(
     filename: &PathBuf,
     part: &String,
     line: &String,
-    string: &str,
+    v: &[&str],
+    column: usize,
 ) -> Result<f64, TrainingDataError> {
+    let string = v.get(column).ok_or_else(|| {
+        TrainingDataError::MissingValue(filename.to_owned(), part.to_owned(), line.to_owned())
+    })?;
     f64::from_str(string).map_err(|e| {
         TrainingDataError::MalformedValue(filename.to_owned(), part.to_owned(), line.to_owned(), e)
     })
@@ -221,7 +413,7 @@ fn parse_float_col(
     column: usize,
 ) -> Result<f64, TrainingDataError> {
     let v: Vec<&str> = line.split_whitespace().collect();
-    parse_float(filename, part, &line, v[column])
+    parse_float(filename, part, &line, &v, column)
 }
 
 fn read_transitions(global: &mut Global, filename: PathBuf) -> Result<(), TrainingDataError> {
@@ -232,7 +424,7 @@ fn read_transitions(global: &mut Global, filename: PathBuf) -> Result<(), Traini
     for _ in 0..NUM_TRANSITIONS {
         let line = next_line(&filename, &mut lines)?;
         let v = line.split_whitespace().collect::<Vec<&str>>();
-        let value = parse_float(&filename, &header, &line, v[1])?.ln();
+        let value = parse_float(&filename, &header, &line, &v, 1)?.ln();
         match v[0] {
             "MM" => global.tr.mm = value,
             "MI" => global.tr.mi = value,
@@ -274,9 +466,47 @@ fn read_transitions(global: &mut Global, filename: PathBuf) -> Result<(), Traini
             parse_float_col(&filename, &header, next_line(&filename, &mut lines)?, 1)?.ln();
     }
 
+    // optional, absent in the original FGS training files
+    if let Some(line) = lines.next() {
+        header = line.map_err(|e| TrainingDataError::Io(filename.to_path_buf(), e))?;
+        if header.trim().is_empty() {
+            return Ok(());
+        }
+        if header.trim() != "TransitionHomopolymer=" {
+            Err(TrainingDataError::UnknownTransitionState)?
+        }
+        for line in lines {
+            let line = line.map_err(|e| TrainingDataError::Io(filename.to_path_buf(), e))?;
+            let v = line.split_whitespace().collect::<Vec<&str>>();
+            if v.is_empty() {
+                continue;
+            }
+            if v.len() < 5 {
+                Err(TrainingDataError::IncompleteTrainingFile)?
+            }
+            let length = usize::from_str(v[0]).map_err(|e| {
+                TrainingDataError::MalformedLength(
+                    filename.to_owned(),
+                    header.to_owned(),
+                    line.to_owned(),
+                    e,
+                )
+            })?;
+            let indel = Indel {
+                mi: parse_float(&filename, &header, &line, &v, 1)?.ln(),
+                ii: parse_float(&filename, &header, &line, &v, 2)?.ln(),
+                md: parse_float(&filename, &header, &line, &v, 3)?.ln(),
+                dd: parse_float(&filename, &header, &line, &v, 4)?.ln(),
+            };
+            global.tr_homopolymer.push((length, indel));
+        }
+        global.tr_homopolymer.sort_by_key(|(length, _)| *length);
+    }
+
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_m_transitions(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     for cg in 0..(CG_MAX - CG_MIN) {
@@ -286,7 +516,7 @@ fn read_m_transitions(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(),
                 let line = next_line(&filename, &mut lines)?;
                 let v: Vec<&str> = line.split_whitespace().collect();
                 for e in 0..ACGT {
-                    locals[cg].e_m[p][c][e] = parse_float(&filename, &header, &line, v[e])?.ln();
+                    locals[cg].e_m[p][c][e] = parse_float(&filename, &header, &line, &v, e)?.ln();
                 }
             }
         }
@@ -295,6 +525,7 @@ fn read_m_transitions(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(),
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_m1_transitions(
     locals: &mut Vec<Local>,
     filename: PathBuf,
@@ -307,7 +538,7 @@ fn read_m1_transitions(
                 let line = next_line(&filename, &mut lines)?;
                 let v: Vec<&str> = line.split_whitespace().collect();
                 for e in 0..ACGT {
-                    locals[cg].e_m1[p][c][e] = parse_float(&filename, &header, &line, v[e])?.ln();
+                    locals[cg].e_m1[p][c][e] = parse_float(&filename, &header, &line, &v, e)?.ln();
                 }
             }
         }
@@ -316,6 +547,7 @@ fn read_m1_transitions(
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_noncoding(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     for cg in 0..(CG_MAX - CG_MIN) {
@@ -324,7 +556,7 @@ fn read_noncoding(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Trai
             let line = next_line(&filename, &mut lines)?;
             let v: Vec<&str> = line.split_whitespace().collect();
             for e2 in 0..ACGT {
-                locals[cg].tr_rr[e1][e2] = parse_float(&filename, &header, &line, v[e2])?.ln();
+                locals[cg].tr_rr[e1][e2] = parse_float(&filename, &header, &line, &v, e2)?.ln();
             }
         }
     }
@@ -332,6 +564,7 @@ fn read_noncoding(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Trai
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_start(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     for cg in 0..(CG_MAX - CG_MIN) {
@@ -340,7 +573,7 @@ fn read_start(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Training
             let line = next_line(&filename, &mut lines)?;
             let v: Vec<&str> = line.split_whitespace().collect();
             for k in 0..TRI_ACGT {
-                locals[cg].tr_s[j][k] = parse_float(&filename, &header, &line, v[k])?.ln();
+                locals[cg].tr_s[j][k] = parse_float(&filename, &header, &line, &v, k)?.ln();
             }
         }
     }
@@ -348,6 +581,7 @@ fn read_start(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Training
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_stop1(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     for cg in 0..(CG_MAX - CG_MIN) {
@@ -356,7 +590,7 @@ fn read_stop1(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Training
             let line = next_line(&filename, &mut lines)?;
             let v: Vec<&str> = line.split_whitespace().collect();
             for k in 0..TRI_ACGT {
-                locals[cg].tr_e1[j][k] = parse_float(&filename, &header, &line, v[k])?.ln();
+                locals[cg].tr_e1[j][k] = parse_float(&filename, &header, &line, &v, k)?.ln();
             }
         }
     }
@@ -364,6 +598,7 @@ fn read_stop1(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Training
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_stop(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     for cg in 0..(CG_MAX - CG_MIN) {
@@ -372,7 +607,7 @@ fn read_stop(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingD
             let line = next_line(&filename, &mut lines)?;
             let v: Vec<&str> = line.split_whitespace().collect();
             for k in 0..TRI_ACGT {
-                locals[cg].tr_e[j][k] = parse_float(&filename, &header, &line, v[k])?.ln();
+                locals[cg].tr_e[j][k] = parse_float(&filename, &header, &line, &v, k)?.ln();
             }
         }
     }
@@ -380,6 +615,7 @@ fn read_stop(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingD
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_start1(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     for cg in 0..(CG_MAX - CG_MIN) {
@@ -388,7 +624,7 @@ fn read_start1(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Trainin
             let line = next_line(&filename, &mut lines)?;
             let v: Vec<&str> = line.split_whitespace().collect();
             for k in 0..TRI_ACGT {
-                locals[cg].tr_s1[j][k] = parse_float(&filename, &header, &line, v[k])?.ln();
+                locals[cg].tr_s1[j][k] = parse_float(&filename, &header, &line, &v, k)?.ln();
             }
         }
     }
@@ -396,6 +632,7 @@ fn read_start1(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), Trainin
     Ok(())
 }
 
+#[allow(clippy::needless_range_loop, clippy::ptr_arg)]
 fn read_pwm(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDataError> {
     let mut lines = lines_from_file(&filename)?;
     let mut line: String;
@@ -404,28 +641,302 @@ fn read_pwm(locals: &mut Vec<Local>, filename: PathBuf) -> Result<(), TrainingDa
         line = next_line(&filename, &mut lines)?;
         let v: Vec<&str> = line.split_whitespace().collect();
         for j in 0..PERIOD {
-            locals[cg].dist_s[j] = parse_float(&filename, &header, &line, v[j])?;
+            locals[cg].dist_s[j] = parse_float(&filename, &header, &line, &v, j)?;
             // no ln
         }
         line = next_line(&filename, &mut lines)?;
         let v: Vec<&str> = line.split_whitespace().collect();
         for j in 0..PERIOD {
-            locals[cg].dist_e[j] = parse_float(&filename, &header, &line, v[j])?;
+            locals[cg].dist_e[j] = parse_float(&filename, &header, &line, &v, j)?;
             // no ln
         }
         line = next_line(&filename, &mut lines)?;
         let v: Vec<&str> = line.split_whitespace().collect();
         for j in 0..PERIOD {
-            locals[cg].dist_s1[j] = parse_float(&filename, &header, &line, v[j])?;
+            locals[cg].dist_s1[j] = parse_float(&filename, &header, &line, &v, j)?;
             // no ln
         }
         line = next_line(&filename, &mut lines)?;
         let v: Vec<&str> = line.split_whitespace().collect();
         for j in 0..PERIOD {
-            locals[cg].dist_e1[j] = parse_float(&filename, &header, &line, v[j])?;
+            locals[cg].dist_e1[j] = parse_float(&filename, &header, &line, &v, j)?;
             // no ln
         }
     }
 
     Ok(())
 }
+
+/// Checks that the transitions and emissions of a model are probabilities
+/// and that the transitions leaving each state sum to 1. The bundled
+/// emission tables are not normalized exactly, so only their range is
+/// checked.
+pub fn validate(global: &Global, locals: &[Local]) -> Vec<ModelProblem> {
+    let mut problems = vec![];
+    let mut check = |name: &dyn Fn() -> String, value: f64| {
+        let p = value.exp();
+        if !(0.0..=1.0 + 1e-9).contains(&p) {
+            problems.push(ModelProblem::NotAProbability(name(), p));
+        }
+    };
+
+    let tr = &global.tr;
+    let transitions = [
+        ("MM", tr.mm),
+        ("MI", tr.mi),
+        ("MD", tr.md),
+        ("II", tr.ii),
+        ("IM", tr.im),
+        ("DD", tr.dd),
+        ("DM", tr.dm),
+        ("GE", tr.ge),
+        ("GG", tr.gg),
+        ("ER", tr.er),
+        ("RS", tr.rs),
+        ("RR", tr.rr),
+        ("ES", tr.es),
+        ("ES1", tr.es1),
+    ];
+    for (name, value) in transitions {
+        check(&|| format!("transition {}", name), value);
+    }
+    let nucleotides = ['A', 'C', 'G', 'T'];
+    for i in 0..ACGT {
+        for j in 0..ACGT {
+            let (a, b) = (nucleotides[i], nucleotides[j]);
+            check(&|| format!("TransitionMI {} {}", a, b), global.tr_mi[i][j]);
+            check(&|| format!("TransitionII {} {}", a, b), global.tr_ii[i][j]);
+        }
+    }
+    for (i, &value) in global.pi.iter().enumerate() {
+        check(&|| format!("initial probability {}", i), value);
+    }
+    for (length, indel) in &global.tr_homopolymer {
+        for (name, value) in [
+            ("MI", indel.mi),
+            ("II", indel.ii),
+            ("MD", indel.md),
+            ("DD", indel.dd),
+        ] {
+            check(
+                &|| format!("homopolymer transition {} from length {}", name, length),
+                value,
+            );
+        }
+    }
+    for (cg, local) in locals.iter().enumerate() {
+        let tables: [(&str, Vec<f64>); 7] = [
+            (
+                "gene",
+                local.e_m.iter().flatten().flatten().copied().collect(),
+            ),
+            (
+                "rgene",
+                local.e_m1.iter().flatten().flatten().copied().collect(),
+            ),
+            ("noncoding", local.tr_rr.iter().flatten().copied().collect()),
+            ("start", local.tr_s.iter().flatten().copied().collect()),
+            ("stop", local.tr_e.iter().flatten().copied().collect()),
+            ("start1", local.tr_s1.iter().flatten().copied().collect()),
+            ("stop1", local.tr_e1.iter().flatten().copied().collect()),
+        ];
+        for (table, values) in &tables {
+            for (i, &value) in values.iter().enumerate() {
+                check(
+                    &|| format!("value {} of {} for CG content {}", i, table, cg + CG_MIN),
+                    value,
+                );
+            }
+        }
+    }
+
+    let groups = [
+        ("MM, MI and MD", vec![tr.mm, tr.mi, tr.md]),
+        ("II and IM", vec![tr.ii, tr.im]),
+        ("DD and DM", vec![tr.dd, tr.dm]),
+        ("GG and GE", vec![tr.gg, tr.ge]),
+        ("ER, ES and ES1", vec![tr.er, tr.es, tr.es1]),
+        // RS is used for the transitions to both strands
+        ("RR and twice RS", vec![tr.rr, tr.rs, tr.rs]),
+    ];
+    for (name, values) in groups {
+        let sum: f64 = values.iter().map(|v| v.exp()).sum();
+        if (sum - 1.0).abs() > 0.01 {
+            problems.push(ModelProblem::NotNormalized(
+                format!("transitions {}", name),
+                sum,
+            ));
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use strum::VariantNames;
+
+    use proptest::prelude::*;
+
+    /// Parses `content` as the transition training file, with the bundled
+    /// emission files.
+    fn parse(name: &str, content: &[u8]) -> Result<(Box<Global>, Vec<Local>), TrainingDataError> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(name), content).unwrap();
+        get_train_from_file(dir.path().to_path_buf(), PathBuf::from(name))
+    }
+
+    /// Lines of a training file with homopolymer transitions, of which some are truncated,
+    /// dropped or have a value replaced.
+    fn edited_training_file() -> impl Strategy<Value = Vec<u8>> {
+        let lines: Vec<String> = include_str!("../tests/data/homopolymer")
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        let count = lines.len();
+        prop::collection::vec(
+            (0..count, 0..4usize, 0..6usize, "[-+.eE0-9a-zA-Z]{0,6}"),
+            0..8,
+        )
+        .prop_map(move |edits| {
+            let mut lines = lines.clone();
+            for (line, edit, column, value) in edits {
+                let line = line.min(lines.len().saturating_sub(1));
+                if lines.is_empty() {
+                    break;
+                }
+                match edit {
+                    0 => lines.truncate(line),
+                    1 => {
+                        lines.remove(line);
+                    }
+                    _ => {
+                        let mut v: Vec<String> =
+                            lines[line].split_whitespace().map(str::to_owned).collect();
+                        if column < v.len() {
+                            v[column] = value;
+                        } else {
+                            v.push(value);
+                        }
+                        lines[line] = v.join("\t");
+                    }
+                }
+            }
+            lines.join("\n").into_bytes()
+        })
+    }
+
+    #[test]
+    fn test_validate_bundled() {
+        for &name in Model::VARIANTS {
+            let (global, locals) =
+                get_train_from_file(PathBuf::from("train"), PathBuf::from(name)).unwrap();
+            assert_eq!(validate(&global, &locals), vec![], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_checksum() {
+        let on_disk = checksum(PathBuf::from("train"), PathBuf::from("complete")).unwrap();
+        let bundled = checksum(PathBuf::from("missing"), PathBuf::from("complete")).unwrap();
+        assert_eq!(on_disk, bundled);
+        assert_eq!(on_disk.len(), 64);
+        assert_ne!(
+            on_disk,
+            checksum(PathBuf::from("train"), PathBuf::from("sanger_5")).unwrap()
+        );
+        assert!(checksum(PathBuf::from("missing"), PathBuf::from("unknown")).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let (mut global, locals) =
+            get_train_from_file(PathBuf::from("train"), PathBuf::from("complete")).unwrap();
+        global.tr.gg = 0.5_f64.ln();
+        global.tr_mi[0][2] = 2.0_f64.ln();
+        assert_eq!(
+            validate(&global, &locals),
+            vec![
+                ModelProblem::NotAProbability("TransitionMI A G".to_string(), 2.0),
+                ModelProblem::NotNormalized("transitions GG and GE".to_string(), 0.501),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let (mut global, _) =
+            get_train_from_file(PathBuf::from("train"), PathBuf::from("454_10")).unwrap();
+        let homopolymer = Indel {
+            mi: 0.1_f64.ln(),
+            ii: 0.1_f64.ln(),
+            md: 0.1_f64.ln(),
+            dd: 0.1_f64.ln(),
+        };
+        global.tr_homopolymer.push((3, homopolymer));
+        let mut model = ModelConfig {
+            match_insertion: Some(0.5),
+            deletion_deletion: Some(1.0),
+            deleted_nucleotide: Some(0.01),
+            max_deletion_length: 2,
+            ..Default::default()
+        };
+        global.apply_overrides(&model).unwrap();
+        assert_eq!(global.tr.mi, 0.5_f64.ln());
+        assert_eq!(global.tr.dd, 0.0);
+        assert_eq!(global.deleted_nucleotide, 0.01_f64.ln());
+        assert_eq!(global.max_deletion_length, 2);
+        // the overrides hold within homopolymer runs too
+        assert_eq!(
+            global.indel(3),
+            Indel {
+                mi: 0.5_f64.ln(),
+                dd: 0.0,
+                ..homopolymer
+            }
+        );
+
+        for probability in [0.0, -0.1, 1.5, f64::NAN] {
+            model.deleted_nucleotide = Some(probability);
+            assert!(global.apply_overrides(&model).is_err(), "{}", probability);
+        }
+        model.deleted_nucleotide = None;
+        model.max_deletion_length = MAX_DELETION_LENGTH + 1;
+        assert_eq!(
+            global.apply_overrides(&model),
+            Err(OverrideError::DeletionTooLong(MAX_DELETION_LENGTH + 1))
+        );
+    }
+
+    #[test]
+    fn test_missing_value() {
+        let content: Vec<&str> = include_str!("../train/complete")
+            .lines()
+            .map(|line| if line.starts_with("MI") { "MI" } else { line })
+            .collect();
+        assert!(matches!(
+            parse("missing", content.join("\n").as_bytes()),
+            Err(TrainingDataError::MissingValue(..))
+        ));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_arbitrary_training_file(content in prop::collection::vec(any::<u8>(), 0..2000)) {
+            let _ = parse("arbitrary", &content);
+        }
+
+        #[test]
+        fn test_edited_training_file(content in edited_training_file()) {
+            if let Ok((global, _)) = parse("edited", &content) {
+                let lengths: Vec<_> = global.tr_homopolymer.iter().map(|(l, _)| *l).collect();
+                prop_assert!(lengths.windows(2).all(|w| w[0] <= w[1]));
+            }
+        }
+    }
+}
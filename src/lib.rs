@@ -1,6 +1,19 @@
 //! Program access to the FragGeneScanRs implementation.
 
+pub mod batch;
+pub mod config;
+pub mod convert;
+pub mod diagnostics;
 pub mod dna;
+pub mod evaluate;
 pub mod gene;
 pub mod hmm;
+pub mod pair;
+pub mod peptide;
+pub mod provenance;
+pub mod runner;
+pub mod stats;
+pub mod summary;
+pub mod train;
+pub mod trellis;
 pub mod viterbi;
@@ -0,0 +1,473 @@
+//! Paired-end reads: mate identifiers, overlap detection and reconciliation
+//! of the predictions of both mates.
+
+use std::fmt;
+
+use crate::dna::Nuc;
+use crate::gene::{Gene, ReadPrediction};
+
+/// Minimum number of overlapping nucleotides for mates to be considered overlapping.
+pub const MIN_OVERLAP: usize = 20;
+/// Maximum fraction of mismatches in the overlap of two mates.
+pub const MAX_MISMATCH_RATE: f64 = 0.1;
+
+/// Header of a mate, ending in `/1` or `/2`.
+pub fn mate_head(mut head: Vec<u8>, mate: usize) -> Vec<u8> {
+    if head.ends_with(b"/1") || head.ends_with(b"/2") {
+        head.truncate(head.len() - 2);
+    }
+    head.extend_from_slice(format!("/{}", mate).as_bytes());
+    head
+}
+
+/// Header of the fragment joining both mates, ending in `/1/2`.
+pub fn pair_head(mut head: Vec<u8>) -> Vec<u8> {
+    if head.ends_with(b"/1") || head.ends_with(b"/2") {
+        head.truncate(head.len() - 2);
+    }
+    head.extend_from_slice(b"/1/2");
+    head
+}
+
+pub fn reverse_complement(seq: &[Nuc]) -> Vec<Nuc> {
+    seq.iter().rev().map(Nuc::rc).collect()
+}
+
+/// Finds the 0-based position in the first mate where the reverse
+/// complement of the second mate starts, preferring the longest overlap.
+/// The second mate may end before the first one, if it is contained in it.
+pub fn overlap(mate1: &[Nuc], mate2: &[Nuc]) -> Option<usize> {
+    let rc2 = reverse_complement(mate2);
+    let max_offset = (mate1.len() + 1).checked_sub(MIN_OVERLAP)?;
+    for offset in 0..max_offset {
+        let overlap = (mate1.len() - offset).min(rc2.len());
+        if overlap < MIN_OVERLAP {
+            continue;
+        }
+        let mismatches = mate1[offset..]
+            .iter()
+            .zip(rc2.iter())
+            .filter(|(&a, &b)| a != b && a != Nuc::N && b != Nuc::N)
+            .count();
+        if mismatches as f64 <= MAX_MISMATCH_RATE * overlap as f64 {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// The consensus fragment covered by both mates, given their overlap.
+/// Mismatches are resolved in favour of the mate with the highest quality
+/// score if both are known, and in favour of the first mate otherwise.
+pub fn merge(
+    mate1: &[Nuc],
+    quality1: Option<&[u8]>,
+    mate2: &[Nuc],
+    quality2: Option<&[u8]>,
+    offset: usize,
+) -> Vec<Nuc> {
+    let rc2 = reverse_complement(mate2);
+    let mut fragment = mate1.to_vec();
+    for (i, &m) in rc2.iter().enumerate().take(mate1.len() - offset) {
+        let n = &mut fragment[offset + i];
+        let better = match (quality1, quality2) {
+            (Some(quality1), Some(quality2)) => {
+                quality2[mate2.len() - 1 - i] > quality1[offset + i]
+            }
+            _ => false,
+        };
+        if *n == Nuc::N || (m != Nuc::N && better) {
+            *n = m;
+        }
+    }
+    if let Some(rest) = rc2.get(mate1.len() - offset..) {
+        fragment.extend_from_slice(rest);
+    }
+    fragment
+}
+
+/// The soft-masked positions of the fragment merged from both mates by
+/// `merge`: those masked in either mate.
+pub fn merge_mask(mask1: &[bool], mask2: &[bool], offset: usize) -> Vec<bool> {
+    if mask1.is_empty() && mask2.is_empty() {
+        return vec![];
+    }
+    let mut mask = mask1.to_vec();
+    mask.resize(mask1.len().max(offset + mask2.len()), false);
+    for (i, &m) in mask2.iter().rev().enumerate() {
+        mask[offset + i] |= m;
+    }
+    mask
+}
+
+/// Projects predictions on a fragment merged from mates of `length1` and
+/// `length2` nucleotides, overlapping at `offset`, back on both mates.
+pub fn project(
+    fragment: &ReadPrediction,
+    head1: Vec<u8>,
+    head2: Vec<u8>,
+    length1: usize,
+    length2: usize,
+    offset: usize,
+) -> (ReadPrediction, ReadPrediction) {
+    let mut mate1 = ReadPrediction::new(head1);
+    let mut mate2 = ReadPrediction::new(head2);
+    mate1.cg = fragment.cg;
+    mate2.cg = fragment.cg;
+    for gene in &fragment.genes {
+        if let Some(gene) = clip(gene, 1, length1) {
+            mate1.genes.push(gene);
+        }
+        if let Some(gene) = clip(gene, offset + 1, offset + length2) {
+            mate2.genes.push(reverse(&gene, offset + length2));
+        }
+    }
+    mate2.genes.reverse();
+    (mate1, mate2)
+}
+
+fn frame(start: usize) -> usize {
+    match start % 3 {
+        0 => 3,
+        frame => frame,
+    }
+}
+
+/// The part of a gene between positions `lo` and `hi` (1-based, inclusive),
+/// keeping its codons aligned with the end it is translated from.
+fn clip(gene: &Gene, lo: usize, hi: usize) -> Option<Gene> {
+    // deleted nucleotides take the position of the next nucleotide
+    let mut positions = Vec::with_capacity(gene.dna.len());
+    let mut position = gene.start;
+    for n in &gene.dna {
+        positions.push(position);
+        if *n != Nuc::Xi {
+            position += 1;
+        }
+    }
+
+    let mut first = positions.iter().position(|&p| p >= lo)?;
+    let mut last = positions.iter().rposition(|&p| p <= hi)? + 1;
+    if gene.forward_strand {
+        let mut skipped = gene.dna[..first]
+            .iter()
+            .filter(|n| !n.is_insertion())
+            .count();
+        while skipped % 3 != 0 && first < last {
+            if !gene.dna[first].is_insertion() {
+                skipped += 1;
+            }
+            first += 1;
+        }
+    } else {
+        let mut skipped = gene.dna[last..]
+            .iter()
+            .filter(|n| !n.is_insertion())
+            .count();
+        while skipped % 3 != 0 && first < last {
+            last -= 1;
+            if !gene.dna[last].is_insertion() {
+                skipped += 1;
+            }
+        }
+    }
+    if first >= last {
+        return None;
+    }
+
+    let start = if gene.start >= lo {
+        gene.start
+    } else {
+        positions[first]
+    };
+    let end = if gene.end <= hi {
+        gene.end
+    } else {
+        positions[last - 1]
+    };
+    let within = |&&p: &&usize| start <= p && p <= end;
+    Some(Gene {
+        start,
+        end,
+        frame: frame(start),
+        score: gene.score,
+        dna: gene.dna[first..last].to_vec(),
+        forward_strand: gene.forward_strand,
+        inserted: gene.inserted.iter().filter(within).copied().collect(),
+        deleted: gene.deleted.iter().filter(within).copied().collect(),
+    })
+}
+
+/// A gene on the reverse complement of a sequence of `length` nucleotides.
+fn reverse(gene: &Gene, length: usize) -> Gene {
+    let start = length + 1 - gene.end;
+    Gene {
+        start,
+        end: length + 1 - gene.start,
+        frame: frame(start),
+        score: gene.score,
+        dna: reverse_complement(&gene.dna),
+        forward_strand: !gene.forward_strand,
+        inserted: gene.inserted.iter().rev().map(|i| length + 1 - i).collect(),
+        // a deletion before a nucleotide ends up after it
+        deleted: gene.deleted.iter().rev().map(|d| length + 2 - d).collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agreement {
+    /// Neither mate contains a gene.
+    NoGenes,
+    /// Only one of the mates contains a gene.
+    SingleMate,
+    /// The genes of both mates are on the same strand of the fragment and,
+    /// where they overlap, in the same frame.
+    Agree,
+    /// The genes of both mates are on different strands of the fragment.
+    StrandConflict,
+    /// Overlapping genes of both mates are in a different frame.
+    FrameConflict,
+}
+
+impl fmt::Display for Agreement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Agreement::NoGenes => "no_genes",
+            Agreement::SingleMate => "single_mate",
+            Agreement::Agree => "agree",
+            Agreement::StrandConflict => "strand_conflict",
+            Agreement::FrameConflict => "frame_conflict",
+        })
+    }
+}
+
+/// Position of a nucleotide in its codon (0, 1 or 2), taking the insertions
+/// and deletions of the gene into account.
+fn codon_position(gene: &Gene, position: usize) -> usize {
+    let (from, to) = if gene.forward_strand {
+        (gene.start, position)
+    } else {
+        (position, gene.end)
+    };
+    let inserted = if gene.forward_strand {
+        gene.inserted
+            .iter()
+            .filter(|&&i| from <= i && i < to)
+            .count()
+    } else {
+        gene.inserted
+            .iter()
+            .filter(|&&i| from < i && i <= to)
+            .count()
+    };
+    let deleted = gene
+        .deleted
+        .iter()
+        .filter(|&&d| from < d && d <= to)
+        .count();
+    (to - from + deleted - inserted) % 3
+}
+
+/// Whether two overlapping genes on the same strand share their reading frame.
+fn same_frame(gene1: &Gene, gene2: &Gene) -> bool {
+    let shared = (gene1.start.max(gene2.start)..=gene1.end.min(gene2.end))
+        .find(|p| !gene1.inserted.contains(p) && !gene2.inserted.contains(p));
+    match shared {
+        Some(p) => codon_position(gene1, p) == codon_position(gene2, p),
+        None => true,
+    }
+}
+
+/// Whether the predictions of two mates of `length2` nucleotides for the
+/// second mate are consistent, given the overlap of the mates if known.
+pub fn agreement(
+    mate1: &ReadPrediction,
+    mate2: &ReadPrediction,
+    length2: usize,
+    offset: Option<usize>,
+) -> Agreement {
+    match (mate1.genes.is_empty(), mate2.genes.is_empty()) {
+        (true, true) => return Agreement::NoGenes,
+        (true, false) | (false, true) => return Agreement::SingleMate,
+        _ => {}
+    }
+
+    let mut result = Agreement::StrandConflict;
+    for gene1 in &mate1.genes {
+        for gene2 in &mate2.genes {
+            if gene1.forward_strand == gene2.forward_strand {
+                continue;
+            }
+            match offset {
+                Some(offset) => {
+                    let gene2 = reverse(gene2, offset + length2);
+                    if gene1.start > gene2.end || gene2.start > gene1.end {
+                        if result == Agreement::StrandConflict {
+                            result = Agreement::Agree;
+                        }
+                    } else if same_frame(gene1, &gene2) {
+                        return Agreement::Agree;
+                    } else {
+                        result = Agreement::FrameConflict;
+                    }
+                }
+                None => {
+                    if result == Agreement::StrandConflict {
+                        result = Agreement::Agree;
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna::dna;
+
+    #[test]
+    fn test_overlap() {
+        let fragment = dna("ATGGCGTTACCGGATTACAGGCTTAACGGCATGCATTGACCGTAGGCTA");
+        let mate1 = fragment[..35].to_vec();
+        let mate2 = reverse_complement(&fragment[10..]);
+        assert_eq!(Some(10), overlap(&mate1, &mate2));
+        assert_eq!(fragment, merge(&mate1, None, &mate2, None, 10));
+        assert_eq!(None, overlap(&mate1, &reverse_complement(&mate1[..10])));
+    }
+
+    #[test]
+    fn test_contained_mate() {
+        let mate1 = dna("ATGGCGTTACCGGATTACAGGCTTAACGGCATGCATTGACCGTAGGCTA");
+        let mate2 = reverse_complement(&mate1[5..30]);
+        assert_eq!(Some(5), overlap(&mate1, &mate2));
+        assert_eq!(mate1, merge(&mate1, None, &mate2, None, 5));
+        let mask = merge_mask(&vec![false; mate1.len()], &vec![true; mate2.len()], 5);
+        assert_eq!(mate1.len(), mask.len());
+        assert_eq!(25, mask.iter().filter(|&&m| m).count());
+    }
+
+    #[test]
+    fn test_merge_quality() {
+        let mate1 = dna("ACGTACGTACGTACGTACGTACGTA");
+        let mut mate2 = reverse_complement(&mate1);
+        mate2[0] = Nuc::G;
+        let (high, low) = (vec![b'I'; 25], vec![b'#'; 25]);
+        assert_eq!(mate1, merge(&mate1, None, &mate2, None, 0));
+        assert_eq!(mate1, merge(&mate1, Some(&high), &mate2, Some(&low), 0));
+        assert_eq!(
+            Nuc::C,
+            merge(&mate1, Some(&low), &mate2, Some(&high), 0)[24]
+        );
+    }
+
+    #[test]
+    fn test_project() {
+        let fragment = dna("AATGAAACCCGGGTTTAAACCCGGGTAAGG");
+        let mut prediction = ReadPrediction::new(b"read/1/2".to_vec());
+        prediction.genes.push(Gene {
+            start: 2,
+            end: 28,
+            frame: 2,
+            score: 1.0,
+            dna: fragment[1..28].to_vec(),
+            forward_strand: true,
+            inserted: vec![],
+            deleted: vec![],
+        });
+        let protein = prediction.genes[0].translate(false);
+        assert_eq!(b"MKPGFKPG", &protein[..]);
+
+        let (mate1, mate2) = project(
+            &prediction,
+            b"read/1".to_vec(),
+            b"read/2".to_vec(),
+            20,
+            20,
+            10,
+        );
+        assert_eq!(
+            (2, 20, true),
+            (
+                mate1.genes[0].start,
+                mate1.genes[0].end,
+                mate1.genes[0].forward_strand
+            )
+        );
+        assert_eq!(protein[..6], mate1.genes[0].translate(false)[..]);
+        assert_eq!(
+            (3, 20, false),
+            (
+                mate2.genes[0].start,
+                mate2.genes[0].end,
+                mate2.genes[0].forward_strand
+            )
+        );
+        assert_eq!(protein[3..], mate2.genes[0].translate(false)[..]);
+    }
+
+    fn prediction(genes: Vec<(usize, usize, bool)>) -> ReadPrediction {
+        let mut prediction = ReadPrediction::new(vec![]);
+        for (start, end, forward_strand) in genes {
+            prediction.genes.push(Gene {
+                start,
+                end,
+                frame: frame(start),
+                score: 1.0,
+                dna: vec![],
+                forward_strand,
+                inserted: vec![],
+                deleted: vec![],
+            });
+        }
+        prediction
+    }
+
+    #[test]
+    fn test_agreement() {
+        // mates of 30 nucleotides, of which the second starts at 10 in the
+        // first, so fragment position p is 41 - p on the second mate
+        let gene1 = prediction(vec![(1, 30, true)]);
+        let empty = prediction(vec![]);
+        assert_eq!(Agreement::NoGenes, agreement(&empty, &empty, 30, Some(10)));
+        assert_eq!(
+            Agreement::SingleMate,
+            agreement(&gene1, &empty, 30, Some(10))
+        );
+        assert_eq!(Agreement::SingleMate, agreement(&empty, &gene1, 30, None));
+
+        // on the fragment from 13, in the frame of gene1
+        let same = prediction(vec![(1, 28, false)]);
+        assert_eq!(Agreement::Agree, agreement(&gene1, &same, 30, Some(10)));
+        // on the fragment from 14, one nucleotide out of frame
+        let shifted = prediction(vec![(1, 27, false)]);
+        assert_eq!(
+            Agreement::FrameConflict,
+            agreement(&gene1, &shifted, 30, Some(10))
+        );
+        // without the overlap, only the strands are compared
+        assert_eq!(Agreement::Agree, agreement(&gene1, &shifted, 30, None));
+        // on the other strand of the fragment
+        let other = prediction(vec![(1, 27, true)]);
+        assert_eq!(
+            Agreement::StrandConflict,
+            agreement(&gene1, &other, 30, Some(10))
+        );
+        assert_eq!(
+            Agreement::StrandConflict,
+            agreement(&gene1, &other, 30, None)
+        );
+        // genes that do not overlap on the fragment
+        let short = prediction(vec![(1, 9, true)]);
+        assert_eq!(Agreement::Agree, agreement(&short, &shifted, 30, Some(10)));
+        assert_eq!("frame_conflict", Agreement::FrameConflict.to_string());
+    }
+
+    #[test]
+    fn test_mate_head() {
+        assert_eq!(b"read/1".to_vec(), mate_head(b"read/1".to_vec(), 1));
+        assert_eq!(b"read/2".to_vec(), mate_head(b"read".to_vec(), 2));
+        assert_eq!(b"read/1/2".to_vec(), pair_head(b"read/1".to_vec()));
+    }
+}
@@ -0,0 +1,137 @@
+//! In silico digestion of predicted proteins into peptides.
+
+extern crate strum;
+use strum_macros::{EnumString, EnumVariantNames};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum Enzyme {
+    /// Cleaves after K or R, unless followed by P.
+    Trypsin,
+    /// Cleaves after K.
+    LysC,
+    /// Cleaves after R, unless followed by P.
+    ArgC,
+    /// Cleaves after E.
+    GluC,
+    /// Cleaves before D.
+    AspN,
+    /// Cleaves after F, W or Y, unless followed by P.
+    Chymotrypsin,
+}
+
+impl Enzyme {
+    /// Whether the enzyme cleaves the bond between these two amino acids.
+    pub fn cleaves(&self, before: u8, after: u8) -> bool {
+        match self {
+            Enzyme::Trypsin => (before == b'K' || before == b'R') && after != b'P',
+            Enzyme::LysC => before == b'K',
+            Enzyme::ArgC => before == b'R' && after != b'P',
+            Enzyme::GluC => before == b'E',
+            Enzyme::AspN => after == b'D',
+            Enzyme::Chymotrypsin => {
+                (before == b'F' || before == b'W' || before == b'Y') && after != b'P'
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Digestion {
+    pub enzyme: Enzyme,
+    /// Maximum number of cleavage sites a peptide may span.
+    pub missed_cleavages: usize,
+    pub min_length: usize,
+    pub max_length: usize,
+}
+
+impl Digestion {
+    /// Digestion without missed cleavages, keeping peptides of 5 to 50 amino acids.
+    pub fn new(enzyme: Enzyme) -> Self {
+        Digestion {
+            enzyme,
+            missed_cleavages: 0,
+            min_length: 5,
+            max_length: 50,
+        }
+    }
+
+    /// Returns the 0-based, end-exclusive positions of the peptides in the protein.
+    /// Peptides never span a stop codon.
+    pub fn digest(&self, protein: &[u8]) -> Vec<(usize, usize)> {
+        let mut peptides = vec![];
+        let mut offset = 0;
+        for fragment in protein.split(|&aa| aa == b'*') {
+            let mut sites = vec![0];
+            for i in 1..fragment.len() {
+                if self.enzyme.cleaves(fragment[i - 1], fragment[i]) {
+                    sites.push(i);
+                }
+            }
+            sites.push(fragment.len());
+
+            for i in 0..sites.len() - 1 {
+                for j in (i + 1)..sites.len().min(i + self.missed_cleavages + 2) {
+                    let length = sites[j] - sites[i];
+                    if length >= self.min_length && length <= self.max_length {
+                        peptides.push((offset + sites[i], offset + sites[j]));
+                    }
+                }
+            }
+            offset += fragment.len() + 1;
+        }
+        peptides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peptides<'a>(digestion: &Digestion, protein: &'a [u8]) -> Vec<&'a [u8]> {
+        digestion
+            .digest(protein)
+            .into_iter()
+            .map(|(start, end)| &protein[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn test_trypsin() {
+        let mut digestion = Digestion::new(Enzyme::Trypsin);
+        digestion.min_length = 1;
+        assert_eq!(
+            vec![&b"MAGK"[..], b"RPLEK", b"AR", b"FSTY"],
+            peptides(&digestion, b"MAGKRPLEKARFSTY")
+        );
+
+        digestion.missed_cleavages = 1;
+        assert_eq!(
+            vec![
+                &b"MAGK"[..],
+                b"MAGKRPLEK",
+                b"RPLEK",
+                b"RPLEKAR",
+                b"AR",
+                b"ARFSTY",
+                b"FSTY"
+            ],
+            peptides(&digestion, b"MAGKRPLEKARFSTY")
+        );
+    }
+
+    #[test]
+    fn test_lengths_and_stops() {
+        let digestion = Digestion::new(Enzyme::Trypsin);
+        assert_eq!(
+            vec![&b"LLEDIK"[..], b"FSTYWV"],
+            peptides(&digestion, b"MAGKLLEDIK*FSTYWV")
+        );
+    }
+
+    #[test]
+    fn test_enzyme_names() {
+        assert_eq!(Ok(Enzyme::AspN), "aspn".parse::<Enzyme>());
+        assert!("pepsin".parse::<Enzyme>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Provenance of the output files: the program, model and options that
+//! produced them.
+
+use std::env;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+extern crate serde;
+use serde::Serialize;
+
+extern crate serde_json;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Provenance {
+    pub program: String,
+    pub version: String,
+    /// Arguments of the run, including the program name.
+    pub command_line: Vec<String>,
+    pub training_file: String,
+    /// Checksum of the model, as computed by `hmm::checksum`.
+    pub model_checksum: String,
+    pub whole_genome: bool,
+    /// Start of the run in UTC, as `YYYY-MM-DDTHH:MM:SSZ`.
+    pub date: String,
+}
+
+impl Provenance {
+    /// The provenance of a run started now with the arguments of this
+    /// process. `SOURCE_DATE_EPOCH`, if set, replaces the current time.
+    pub fn new(training_file: &str, model_checksum: &str, whole_genome: bool) -> Self {
+        let time = match env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            Some(seconds) => UNIX_EPOCH + Duration::from_secs(seconds),
+            None => SystemTime::now(),
+        };
+        Provenance {
+            program: "FragGeneScanRs".to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            command_line: env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            training_file: training_file.to_owned(),
+            model_checksum: model_checksum.to_owned(),
+            whole_genome,
+            date: utc_date(time),
+        }
+    }
+
+    /// Key-value pairs of the provenance, in the order they are written.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("processor", format!("{} {}", self.program, self.version)),
+            ("command-line", quote(&self.command_line)),
+            ("training-file", self.training_file.clone()),
+            ("model-checksum", self.model_checksum.clone()),
+            (
+                "whole-genome",
+                (if self.whole_genome { "1" } else { "0" }).to_owned(),
+            ),
+            ("date", self.date.clone()),
+        ]
+    }
+
+    /// Writes a line `{prefix}{key} {value}` per field, e.g. with prefix
+    /// `#!` for GFF3 pragmas or `# ` for comments in the metadata.
+    pub fn write_comments<W: Write>(&self, mut writer: W, prefix: &str) -> io::Result<()> {
+        for (key, value) in self.fields() {
+            writeln!(writer, "{}{} {}", prefix, key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the provenance as a JSON object, for outputs in formats
+    /// without comments.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)
+    }
+}
+
+/// Joins the arguments into a single line, quoting those that the shell
+/// would split or interpret.
+fn quote(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./,:=+@%".contains(c))
+            {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats a time as a UTC date, with the civil calendar conversion of
+/// Howard Hinnant's `civil_from_days`.
+fn utc_date(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = ((seconds / 86400) as i64, seconds % 86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utc_date() {
+        assert_eq!(utc_date(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            utc_date(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            utc_date(UNIX_EPOCH + Duration::from_secs(1_792_368_000)),
+            "2026-10-19T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let provenance = Provenance {
+            program: "FragGeneScanRs".to_owned(),
+            version: "1.1.0".to_owned(),
+            command_line: ["FragGeneScanRs", "-s", "my reads.fna", "-w", "1"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            training_file: "complete".to_owned(),
+            model_checksum: "abc".to_owned(),
+            whole_genome: true,
+            date: "2026-10-19T00:00:00Z".to_owned(),
+        };
+        let mut buf = vec![];
+        provenance.write_comments(&mut buf, "#!").unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "#!processor FragGeneScanRs 1.1.0\n\
+             #!command-line FragGeneScanRs -s 'my reads.fna' -w 1\n\
+             #!training-file complete\n\
+             #!model-checksum abc\n\
+             #!whole-genome 1\n\
+             #!date 2026-10-19T00:00:00Z\n"
+        );
+
+        let mut buf = vec![];
+        provenance.write_json(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["training_file"], "complete");
+        assert_eq!(json["command_line"][3], "-w");
+    }
+}
@@ -0,0 +1,179 @@
+//! Prediction of genes on a thread pool owned by the caller, so that
+//! several runs, with different numbers of threads, can share a process.
+
+use std::cell::RefCell;
+
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+use crate::dna::Nuc;
+use crate::gene::{GeneFilter, ReadPrediction};
+use crate::hmm;
+use crate::trellis::Trellis;
+use crate::viterbi::{viterbi, viterbi_long_read, viterbi_reusing, ReadError, Scratch};
+
+thread_local! {
+    /// Buffers of the decoder, reused by the short reads decoded on a thread.
+    static SCRATCH: RefCell<Scratch> = RefCell::new(Scratch::default());
+}
+
+pub struct Runner {
+    pool: ThreadPool,
+    global: Box<hmm::Global>,
+    locals: Vec<hmm::Local>,
+    /// Predict complete genes on complete genomic sequences.
+    pub whole_genome: bool,
+    /// Predict complete genes on long, error-prone reads.
+    pub long_reads: bool,
+    pub filter: GeneFilter,
+    pub trellis: Trellis,
+}
+
+impl Runner {
+    /// A runner for short reads with its own pool of `threads` threads.
+    pub fn new(
+        global: Box<hmm::Global>,
+        locals: Vec<hmm::Local>,
+        threads: usize,
+    ) -> Result<Self, ThreadPoolBuildError> {
+        Ok(Runner {
+            pool: ThreadPoolBuilder::new().num_threads(threads).build()?,
+            global,
+            locals,
+            whole_genome: false,
+            long_reads: false,
+            filter: GeneFilter::new(false),
+            trellis: Trellis::Full,
+        })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Whether complete genes are predicted, for translation and output.
+    pub fn complete_genes(&self) -> bool {
+        self.whole_genome || self.long_reads
+    }
+
+    /// Runs `op` on the thread pool of this runner, so that parallel
+    /// iterators in it use these threads.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        self.pool.install(op)
+    }
+
+    /// Predicts the genes on a single read, on the calling thread. Positions
+    /// set in `mask` (if not empty) are not allowed in genes.
+    pub fn decode(
+        &self,
+        head: Vec<u8>,
+        seq: Vec<Nuc>,
+        mask: &[bool],
+    ) -> Result<ReadPrediction, ReadError> {
+        if self.long_reads {
+            viterbi_long_read(
+                &self.global,
+                &self.locals,
+                head,
+                seq,
+                mask,
+                &self.filter,
+                self.trellis,
+            )
+        } else {
+            viterbi(
+                &self.global,
+                &self.locals,
+                head,
+                seq,
+                mask,
+                self.whole_genome,
+                &self.filter,
+                self.trellis,
+            )
+        }
+    }
+
+    /// Predicts the genes on reads with their masks, one after the other on
+    /// the calling thread. Short reads reuse the buffers kept by the thread,
+    /// which is all that distinguishes this from calling `decode` on each.
+    /// Complete genomic sequences and long reads are decoded without them,
+    /// so their large trellises are not kept.
+    pub fn decode_reusing(
+        &self,
+        reads: Vec<(Vec<u8>, Vec<Nuc>, Vec<bool>)>,
+    ) -> Vec<Result<ReadPrediction, ReadError>> {
+        if self.complete_genes() {
+            return reads
+                .into_iter()
+                .map(|(head, seq, mask)| self.decode(head, seq, &mask))
+                .collect();
+        }
+        SCRATCH.with(|scratch| {
+            viterbi_reusing(
+                &mut scratch.borrow_mut(),
+                &self.global,
+                &self.locals,
+                reads,
+                self.whole_genome,
+                &self.filter,
+                self.trellis,
+            )
+        })
+    }
+
+    /// Predicts the genes on a batch of reads on the thread pool, in the
+    /// order of the reads.
+    pub fn predict(
+        &self,
+        reads: Vec<(Vec<u8>, Vec<Nuc>)>,
+    ) -> Vec<Result<ReadPrediction, ReadError>> {
+        let chunk = reads.len().div_ceil(4 * self.threads()).max(1);
+        self.install(|| {
+            reads
+                .into_par_iter()
+                .map(|(head, seq)| (head, seq, vec![]))
+                .chunks(chunk)
+                .flat_map_iter(|reads| self.decode_reusing(reads))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::dna::dna;
+
+    #[test]
+    fn test_runners() {
+        let runner = |threads| {
+            let (global, locals) =
+                hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("454_10")).unwrap();
+            Runner::new(global, locals, threads).unwrap()
+        };
+        let reads = || {
+            vec![
+                (b"read1".to_vec(), dna("TGTTCGCTGGCGGTGCTTTAGGGGGAGATGCGCAGAATGTCTCAGCCCTGCAAGGGGGTACTCTAGACATGGTGGTATTGAATTCCGGTATCTTAGCTTCGCAAGTGAAAGAGTTTGCCGTGTATGACTTTCCGTTCATGTTCAATAACTCTGAAGAAGCCGATAAAGTCGTCGACGGTGAATTTGGTAAGGCTTTGCACGCGAAATTAGAAGAGAAAGGTATTATCGGTTTAGCCTATTGGGAGTTGGGCTTCCGCGATATGACTAATAGCAAACACCCTATCACTAAGGTCGAGGATATTAAAGGTCTGAAACTGCGTGTTATCCC")),
+                (b"read2".to_vec(), dna("AC")),
+                (b"read3".to_vec(), dna("GGGATAACACGCAGTTTCAGACCTTTAATATCCTCGACCTTAGTGATAGGGTGTTTGCTATTAGTCATATCGCGGAAGCCCAACTCCCAATAGGCTAAACCGATAATACCTTTCTCTTCTAATTTCGCGTGCAAAGCCTTACCAAATTCACCGTCGACGACTTTATCGGCTTCTTCAGAGTTATTGAACATGAACGGAAAGTCATACACGGCAAACTCTTTCACTTGCGAAGCTAAGATACCGGAATTCAATACCACCATGTCTAGAGTACCCCCTTGCAGGGCTGAGACATTCTGCGCATCTCCCCCTAAAGCACCGCCAGCGAACA")),
+            ]
+        };
+
+        let single = runner(1);
+        let expected = single.predict(reads());
+        assert_eq!(Err(ReadError::TooShort(2)), expected[1]);
+        assert_eq!(1, expected[0].as_ref().unwrap().genes.len());
+        assert_eq!(expected, single.predict(reads()));
+
+        let multiple = runner(3);
+        assert_eq!(3, multiple.threads());
+        assert_eq!(expected, multiple.predict(reads()));
+    }
+}
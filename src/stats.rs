@@ -0,0 +1,132 @@
+//! Statistics of input sequences, to choose a training file and options
+//! before running a prediction.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+extern crate serde;
+use serde::Serialize;
+
+use crate::dna::{count_cg_content, Nuc, CG_MIN};
+use crate::viterbi;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct SequenceStats {
+    pub sequences: usize,
+    pub bases: usize,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// Length such that the sequences at least this long contain half of
+    /// the bases.
+    pub n50: Option<usize>,
+    /// Bases that are C or G, in either case.
+    pub cg_bases: usize,
+    /// Bases other than A, C, G or T, such as N and other IUPAC codes.
+    pub ambiguous_bases: usize,
+    /// Lowercase (soft-masked) bases.
+    pub soft_masked_bases: usize,
+    /// Sequences that are too short or have no A, C, G or T, and would be
+    /// skipped by the prediction.
+    pub undecodable_sequences: usize,
+    /// Number of sequences per GC content (in percent) of the model that
+    /// would be used.
+    pub gc_bins: BTreeMap<usize, usize>,
+    #[serde(skip)]
+    lengths: Vec<usize>,
+}
+
+impl SequenceStats {
+    pub fn add(&mut self, seq: &[u8]) {
+        self.sequences += 1;
+        self.bases += seq.len();
+        self.lengths.push(seq.len());
+        self.min_length = Some(self.min_length.map_or(seq.len(), |l| l.min(seq.len())));
+        self.max_length = Some(self.max_length.map_or(seq.len(), |l| l.max(seq.len())));
+        for &n in seq {
+            match n {
+                b'C' | b'G' | b'c' | b'g' => self.cg_bases += 1,
+                b'A' | b'T' | b'a' | b't' => {}
+                _ => self.ambiguous_bases += 1,
+            }
+            if n.is_ascii_lowercase() {
+                self.soft_masked_bases += 1;
+            }
+        }
+        let nseq: Vec<Nuc> = seq
+            .iter()
+            .map(u8::to_ascii_uppercase)
+            .map(Nuc::from)
+            .collect();
+        if viterbi::check(&nseq).is_ok() {
+            *self
+                .gc_bins
+                .entry(count_cg_content(&nseq) + CG_MIN)
+                .or_insert(0) += 1;
+        } else {
+            self.undecodable_sequences += 1;
+        }
+    }
+
+    /// Computes the N50 of the sequences added so far.
+    pub fn finish(&mut self) {
+        self.lengths.sort_unstable_by(|a, b| b.cmp(a));
+        let mut bases = 0;
+        self.n50 = self
+            .lengths
+            .iter()
+            .find(|&&length| {
+                bases += length;
+                2 * bases >= self.bases
+            })
+            .copied();
+    }
+}
+
+impl fmt::Display for SequenceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} sequences ({} bases)", self.sequences, self.bases)?;
+        if let (Some(min), Some(max), Some(n50)) = (self.min_length, self.max_length, self.n50) {
+            writeln!(f, "  length: {} to {} (N50 {})", min, max, n50)?;
+        }
+        if self.undecodable_sequences > 0 {
+            writeln!(f, "  undecodable: {}", self.undecodable_sequences)?;
+        }
+        if self.bases > 0 {
+            let percent = |count: usize| 100.0 * count as f64 / self.bases as f64;
+            writeln!(f, "GC content:  {:.2}%", percent(self.cg_bases))?;
+            writeln!(f, "Ambiguous:   {:.2}%", percent(self.ambiguous_bases))?;
+            writeln!(f, "Soft-masked: {:.2}%", percent(self.soft_masked_bases))?;
+        }
+        writeln!(f, "Sequences per GC content model:")?;
+        for (cg, count) in &self.gc_bins {
+            writeln!(f, "  {}%: {}", cg, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna::CG_MAX;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = SequenceStats::default();
+        for seq in [&b"ACGTACGTNN"[..], b"gcgc", b"NN", b"ATATATATAT"] {
+            stats.add(seq);
+        }
+        stats.finish();
+        assert_eq!(stats.sequences, 4);
+        assert_eq!(stats.bases, 26);
+        assert_eq!((stats.min_length, stats.max_length), (Some(2), Some(10)));
+        assert_eq!(stats.n50, Some(10));
+        assert_eq!(stats.cg_bases, 8);
+        assert_eq!(stats.ambiguous_bases, 4);
+        assert_eq!(stats.soft_masked_bases, 4);
+        assert_eq!(stats.undecodable_sequences, 1);
+        assert_eq!(stats.gc_bins.values().sum::<usize>(), 3);
+        // soft-masked bases count towards the CG content, as in prediction
+        assert_eq!(stats.gc_bins[&(CG_MAX - 1)], 1);
+    }
+}
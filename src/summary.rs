@@ -0,0 +1,251 @@
+//! Statistics aggregated over all sequences of a run.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+extern crate serde;
+use serde::Serialize;
+
+use crate::dna::CG_MIN;
+use crate::gene::ReadPrediction;
+
+/// Width (in nucleotides) of the bins of the gene length distribution.
+pub const LENGTH_BIN: usize = 50;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub sequences: usize,
+    pub bases: usize,
+    pub sequences_without_genes: usize,
+    pub sequences_with_one_gene: usize,
+    pub sequences_with_multiple_genes: usize,
+    /// Sequences that could not be decoded, e.g. because they are too short.
+    pub skipped_sequences: usize,
+    /// Records that could not be parsed. The input is not read past a
+    /// malformed record, so the records after it are missing.
+    pub malformed_records: usize,
+    pub genes: usize,
+    pub forward_genes: usize,
+    pub reverse_genes: usize,
+    /// Genes in which at least one insertion or deletion was corrected.
+    pub frameshifted_genes: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+    /// Number of sequences per GC content (in percent) of the model used.
+    pub gc_bins: BTreeMap<usize, usize>,
+    /// Number of genes per length, binned by the lower bound of `LENGTH_BIN` nucleotides.
+    pub gene_lengths: BTreeMap<usize, usize>,
+}
+
+impl Summary {
+    /// Count a sequence of `length` nucleotides, with the GC bin of its
+    /// prediction if it was decoded.
+    pub fn add(&mut self, read_prediction: &ReadPrediction, length: usize) {
+        self.sequences += 1;
+        self.bases += length;
+        match read_prediction.genes.len() {
+            0 => self.sequences_without_genes += 1,
+            1 => self.sequences_with_one_gene += 1,
+            _ => self.sequences_with_multiple_genes += 1,
+        }
+        if let Some(cg) = read_prediction.cg {
+            *self.gc_bins.entry(cg + CG_MIN).or_insert(0) += 1;
+        }
+        for gene in &read_prediction.genes {
+            self.genes += 1;
+            if gene.forward_strand {
+                self.forward_genes += 1;
+            } else {
+                self.reverse_genes += 1;
+            }
+            if !gene.inserted.is_empty() || !gene.deleted.is_empty() {
+                self.frameshifted_genes += 1;
+            }
+            self.insertions += gene.inserted.len();
+            self.deletions += gene.deleted.len();
+            let length = gene.end + 1 - gene.start;
+            *self
+                .gene_lengths
+                .entry(length / LENGTH_BIN * LENGTH_BIN)
+                .or_insert(0) += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: Summary) {
+        self.sequences += other.sequences;
+        self.bases += other.bases;
+        self.sequences_without_genes += other.sequences_without_genes;
+        self.sequences_with_one_gene += other.sequences_with_one_gene;
+        self.sequences_with_multiple_genes += other.sequences_with_multiple_genes;
+        self.skipped_sequences += other.skipped_sequences;
+        self.malformed_records += other.malformed_records;
+        self.genes += other.genes;
+        self.forward_genes += other.forward_genes;
+        self.reverse_genes += other.reverse_genes;
+        self.frameshifted_genes += other.frameshifted_genes;
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+        for (cg, count) in other.gc_bins {
+            *self.gc_bins.entry(cg).or_insert(0) += count;
+        }
+        for (length, count) in other.gene_lengths {
+            *self.gene_lengths.entry(length).or_insert(0) += count;
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Processed {} sequences ({} bases)",
+            self.sequences, self.bases
+        )?;
+        writeln!(f, "  without genes:       {}", self.sequences_without_genes)?;
+        writeln!(f, "  with one gene:       {}", self.sequences_with_one_gene)?;
+        writeln!(
+            f,
+            "  with multiple genes: {}",
+            self.sequences_with_multiple_genes
+        )?;
+        if self.skipped_sequences > 0 {
+            writeln!(f, "  skipped:             {}", self.skipped_sequences)?;
+        }
+        if self.malformed_records > 0 {
+            writeln!(
+                f,
+                "Input truncated at a malformed record ({} reported)",
+                self.malformed_records
+            )?;
+        }
+        writeln!(
+            f,
+            "Predicted {} genes ({} forward, {} reverse)",
+            self.genes, self.forward_genes, self.reverse_genes
+        )?;
+        writeln!(
+            f,
+            "  with corrected frameshifts: {} ({} insertions, {} deletions)",
+            self.frameshifted_genes, self.insertions, self.deletions
+        )?;
+        writeln!(f, "Sequences per GC content model:")?;
+        for (cg, count) in &self.gc_bins {
+            writeln!(f, "  {}%: {}", cg, count)?;
+        }
+        writeln!(f, "Gene length distribution:")?;
+        for (length, count) in &self.gene_lengths {
+            writeln!(f, "  {}-{}: {}", length, length + LENGTH_BIN - 1, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::gene::Gene;
+
+    fn gene(start: usize, end: usize, forward_strand: bool, inserted: Vec<usize>) -> Gene {
+        Gene {
+            start,
+            end,
+            frame: 1,
+            score: 1.0,
+            dna: vec![],
+            forward_strand,
+            inserted,
+            deleted: vec![],
+        }
+    }
+
+    fn prediction(genes: Vec<Gene>, cg: Option<usize>) -> ReadPrediction {
+        let mut read_prediction = ReadPrediction::new(b"read".to_vec());
+        read_prediction.genes = genes;
+        read_prediction.cg = cg;
+        read_prediction
+    }
+
+    #[test]
+    fn test_add() {
+        let mut summary = Summary::default();
+        summary.add(&prediction(vec![], Some(10)), 100);
+        summary.add(&prediction(vec![gene(1, 99, true, vec![5])], Some(10)), 100);
+        summary.add(
+            &prediction(
+                vec![gene(1, 60, true, vec![]), gene(71, 220, false, vec![])],
+                Some(20),
+            ),
+            250,
+        );
+        summary.add(&prediction(vec![], None), 2);
+
+        assert_eq!(summary.sequences, 4);
+        assert_eq!(summary.bases, 452);
+        assert_eq!(summary.sequences_without_genes, 2);
+        assert_eq!(summary.sequences_with_one_gene, 1);
+        assert_eq!(summary.sequences_with_multiple_genes, 1);
+        assert_eq!((summary.forward_genes, summary.reverse_genes), (2, 1));
+        assert_eq!(summary.frameshifted_genes, 1);
+        assert_eq!((summary.insertions, summary.deletions), (1, 0));
+        assert_eq!(
+            summary.gc_bins,
+            vec![(10 + CG_MIN, 2), (20 + CG_MIN, 1)]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            summary.gene_lengths,
+            vec![(50, 2), (150, 1)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let reads = [
+            (prediction(vec![gene(1, 99, true, vec![])], Some(10)), 100),
+            (prediction(vec![], Some(12)), 80),
+            (
+                prediction(vec![gene(3, 200, false, vec![7])], Some(10)),
+                210,
+            ),
+        ];
+        let mut whole = Summary::default();
+        for (read_prediction, length) in &reads {
+            whole.add(read_prediction, *length);
+        }
+        let mut first = Summary::default();
+        first.add(&reads[0].0, reads[0].1);
+        let mut second = Summary::default();
+        second.add(&reads[1].0, reads[1].1);
+        second.add(&reads[2].0, reads[2].1);
+        first.merge(second);
+        assert_eq!(first, whole);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut summary = Summary::default();
+        summary.add(&prediction(vec![gene(1, 99, true, vec![5])], Some(10)), 100);
+        summary.skipped_sequences = 1;
+        summary.malformed_records = 1;
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "Processed 1 sequences (100 bases)\n  \
+                 without genes:       0\n  \
+                 with one gene:       1\n  \
+                 with multiple genes: 0\n  \
+                 skipped:             1\n\
+                 Input truncated at a malformed record (1 reported)\n\
+                 Predicted 1 genes (1 forward, 0 reverse)\n  \
+                 with corrected frameshifts: 1 (1 insertions, 0 deletions)\n\
+                 Sequences per GC content model:\n  \
+                 {}%: 1\n\
+                 Gene length distribution:\n  \
+                 50-99: 1\n",
+                10 + CG_MIN
+            )
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Estimation of the gene structure transitions of a training file from
+//! reference annotations.
+
+use std::collections::HashMap;
+use std::fmt;
+
+extern crate serde;
+use serde::Serialize;
+
+extern crate thiserror;
+use thiserror::Error;
+
+use crate::evaluate::{Annotation, Annotations};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TrainError {
+    #[error(
+        "unknown length of sequence {0}; add a ##sequence-region pragma or pass the sequences"
+    )]
+    UnknownLength(String),
+    #[error("gene {1}-{2} lies outside sequence {0}")]
+    OutOfBounds(String, usize, usize),
+    #[error("no genes and noncoding regions to estimate the transitions from")]
+    NoGenes,
+}
+
+/// Counts of the transitions between genes and noncoding regions in
+/// annotated sequences. Overlapping genes are counted as adjacent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Structure {
+    /// Number of genes.
+    pub genes: usize,
+    /// Number of codons in the genes.
+    pub codons: usize,
+    /// Genes followed by a noncoding region.
+    pub to_noncoding: usize,
+    /// Genes followed directly by a gene on the same strand.
+    pub to_same_strand: usize,
+    /// Genes followed directly by a gene on the other strand.
+    pub to_other_strand: usize,
+    /// Noncoding regions followed by a gene.
+    pub noncoding_regions: usize,
+    /// Number of noncoding nucleotides.
+    pub noncoding: usize,
+}
+
+impl Structure {
+    /// Counts the transitions in the annotated genes, on sequences of which
+    /// the lengths are known.
+    pub fn count(annotations: &Annotations) -> Result<Self, TrainError> {
+        let mut sequences: HashMap<&str, Vec<&Annotation>> = annotations
+            .lengths
+            .keys()
+            .map(|seqid| (seqid.as_str(), vec![]))
+            .collect();
+        for gene in &annotations.genes {
+            sequences.entry(&gene.seqid).or_default().push(gene);
+        }
+
+        let mut structure = Structure::default();
+        for (seqid, mut genes) in sequences {
+            let length = *annotations
+                .lengths
+                .get(seqid)
+                .ok_or_else(|| TrainError::UnknownLength(seqid.to_owned()))?;
+            genes.sort_by_key(|gene| gene.start);
+
+            let mut covered = 0;
+            let mut previous: Option<&Annotation> = None;
+            for gene in genes {
+                if gene.start < 1 || gene.start > gene.end || gene.end > length {
+                    return Err(TrainError::OutOfBounds(
+                        seqid.to_owned(),
+                        gene.start,
+                        gene.end,
+                    ));
+                }
+                if gene.start > covered + 1 {
+                    structure.noncoding += gene.start - covered - 1;
+                    structure.noncoding_regions += 1;
+                    if previous.is_some() {
+                        structure.to_noncoding += 1;
+                    }
+                } else if let Some(previous) = previous {
+                    if previous.forward_strand == gene.forward_strand {
+                        structure.to_same_strand += 1;
+                    } else {
+                        structure.to_other_strand += 1;
+                    }
+                }
+                structure.genes += 1;
+                structure.codons += (gene.end - gene.start + 1) / 3;
+                covered = covered.max(gene.end);
+                previous = Some(gene);
+            }
+            if covered < length {
+                structure.noncoding += length - covered;
+                if previous.is_some() {
+                    structure.to_noncoding += 1;
+                }
+            }
+        }
+
+        if structure.codons == 0 || structure.noncoding_regions == 0 {
+            return Err(TrainError::NoGenes);
+        }
+        Ok(structure)
+    }
+
+    /// The transition probabilities `GG`, `GE`, `ER`, `ES`, `ES1`, `RS` and
+    /// `RR`, as named in the training files. One pseudocount is added to
+    /// each of the transitions leaving a gene.
+    pub fn transitions(&self) -> Vec<(&'static str, f64)> {
+        let ge = self.genes as f64 / self.codons as f64;
+        let ends = (self.to_noncoding + self.to_same_strand + self.to_other_strand + 3) as f64;
+        // RS is used for the transitions to genes on both strands
+        let rs = self.noncoding_regions as f64 / self.noncoding as f64 / 2.0;
+        vec![
+            ("GG", 1.0 - ge),
+            ("GE", ge),
+            ("ER", (self.to_noncoding + 1) as f64 / ends),
+            ("ES", (self.to_same_strand + 1) as f64 / ends),
+            ("ES1", (self.to_other_strand + 1) as f64 / ends),
+            ("RS", rs),
+            ("RR", 1.0 - 2.0 * rs),
+        ]
+    }
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Counted {} genes ({} codons)", self.genes, self.codons)?;
+        writeln!(f, "  followed by noncoding:    {}", self.to_noncoding)?;
+        writeln!(f, "  followed by same strand:  {}", self.to_same_strand)?;
+        writeln!(f, "  followed by other strand: {}", self.to_other_strand)?;
+        writeln!(
+            f,
+            "Counted {} noncoding regions ({} bases)",
+            self.noncoding_regions, self.noncoding
+        )
+    }
+}
+
+/// Replaces the values of the given transitions in the `Transition=`
+/// section of the lines of a training file, keeping all other lines.
+pub fn replace_transitions(lines: &[String], transitions: &[(&str, f64)]) -> Vec<String> {
+    let mut section = "";
+    lines
+        .iter()
+        .map(|line| {
+            let name = line.split_whitespace().next().unwrap_or("");
+            if name.ends_with('=') {
+                section = name;
+            } else if section == "Transition=" {
+                if let Some((_, value)) = transitions.iter().find(|(n, _)| *n == name) {
+                    return format!("{}\t{:.6}", name, value);
+                }
+            }
+            line.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gene(start: usize, end: usize, forward_strand: bool) -> Annotation {
+        Annotation {
+            seqid: "seq".to_string(),
+            start,
+            end,
+            forward_strand,
+        }
+    }
+
+    #[test]
+    fn test_count() {
+        let annotations = Annotations {
+            genes: vec![
+                gene(1101, 1400, false),
+                gene(101, 400, true),
+                gene(401, 700, true),
+                gene(691, 1000, false),
+            ],
+            lengths: vec![("seq".to_string(), 1500)].into_iter().collect(),
+        };
+        assert_eq!(
+            Structure::count(&annotations),
+            Ok(Structure {
+                genes: 4,
+                codons: 403,
+                to_noncoding: 2,
+                to_same_strand: 1,
+                to_other_strand: 1,
+                noncoding_regions: 2,
+                noncoding: 300,
+            })
+        );
+        let transitions = Structure::count(&annotations).unwrap().transitions();
+        assert_eq!(transitions[1], ("GE", 4.0 / 403.0));
+        assert_eq!(transitions[2], ("ER", 3.0 / 7.0));
+        assert_eq!(transitions[5], ("RS", 1.0 / 300.0));
+
+        let mut annotations = annotations;
+        annotations.lengths.clear();
+        assert_eq!(
+            Structure::count(&annotations),
+            Err(TrainError::UnknownLength("seq".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_replace_transitions() {
+        let lines: Vec<String> = ["Transition=", "GG\t0.9990", "GE\t0.0010", "PI=", "GE\t0.1"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            replace_transitions(&lines, &[("GE", 0.25)]),
+            vec![
+                "Transition=",
+                "GG\t0.9990",
+                "GE\t0.250000",
+                "PI=",
+                "GE\t0.1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Storage of the trellis filled by the forward pass of the decoder.
+
+use std::ops::{Index, IndexMut};
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::hmm::State;
+
+/// How the trellis is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Trellis {
+    /// Scores in double precision and a byte per traceback entry.
+    #[default]
+    Full,
+    /// Scores in single precision and 5 bits per traceback entry, which
+    /// about halves the memory used per nucleotide. The forward pass still
+    /// computes in double precision, so only the scores of the genes and
+    /// the choice of the final state are affected by the rounding.
+    Compact,
+}
+
+/// Columns of the trellis, one per nucleotide, with a value per state.
+/// While processing nucleotide `t`, the forward pass only accesses the
+/// columns `t - 5` to `t + 2` through indexing, and finishes column `t`
+/// afterwards.
+pub trait Columns<T: Copy>: Default + IndexMut<usize, Output = [T; State::COUNT]> {
+    /// Replaces the columns by `len` columns with the same value for each
+    /// of them, keeping the memory allocated for earlier sequences.
+    fn reset(&mut self, len: usize, initial: [T; State::COUNT]);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Columns `t - 1` and `t`.
+    fn pair_mut(&mut self, t: usize) -> (&[T; State::COUNT], &mut [T; State::COUNT]);
+
+    /// Marks column `t` as final. Columns are finished in order.
+    fn finish(&mut self, t: usize);
+
+    /// The value of a state in a finished column.
+    fn get(&self, t: usize, state: State) -> T;
+}
+
+impl<T: Copy> Columns<T> for Vec<[T; State::COUNT]> {
+    fn reset(&mut self, len: usize, initial: [T; State::COUNT]) {
+        self.clear();
+        self.resize(len, initial);
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn pair_mut(&mut self, t: usize) -> (&[T; State::COUNT], &mut [T; State::COUNT]) {
+        let (before, after) = self.split_at_mut(t);
+        (&before[t - 1], &mut after[0])
+    }
+
+    fn finish(&mut self, _: usize) {}
+
+    fn get(&self, t: usize, state: State) -> T {
+        self[t][state]
+    }
+}
+
+/// Number of columns kept at full size while the forward pass works on them.
+const WORKING: usize = 8;
+
+/// The columns the forward pass works on, in a ring.
+#[derive(Debug, Clone)]
+struct Working<T> {
+    columns: [[T; State::COUNT]; WORKING],
+    initial: [T; State::COUNT],
+}
+
+impl<T: Copy + Default> Default for Working<T> {
+    fn default() -> Self {
+        Working::new([T::default(); State::COUNT])
+    }
+}
+
+impl<T: Copy> Working<T> {
+    fn new(initial: [T; State::COUNT]) -> Self {
+        Working {
+            columns: [initial; WORKING],
+            initial,
+        }
+    }
+
+    fn pair_mut(&mut self, t: usize) -> (&[T; State::COUNT], &mut [T; State::COUNT]) {
+        let (previous, current) = ((t - 1) % WORKING, t % WORKING);
+        if current == 0 {
+            let (first, rest) = self.columns.split_at_mut(1);
+            (&rest[previous - 1], &mut first[0])
+        } else {
+            let (before, after) = self.columns.split_at_mut(current);
+            (&before[previous], &mut after[0])
+        }
+    }
+
+    /// Column `t` is finished, so column `t - 5` is no longer read and its
+    /// place is taken by column `t + 3`, which is first written next.
+    fn finish(&mut self, t: usize) {
+        self.columns[(t + 3) % WORKING] = self.initial;
+    }
+}
+
+/// Scores stored in single precision once their column is finished.
+#[derive(Debug, Clone, Default)]
+pub struct SinglePrecision {
+    working: Working<f64>,
+    columns: Vec<[f32; State::COUNT]>,
+    len: usize,
+}
+
+impl Index<usize> for SinglePrecision {
+    type Output = [f64; State::COUNT];
+
+    fn index(&self, t: usize) -> &Self::Output {
+        &self.working.columns[t % WORKING]
+    }
+}
+
+impl IndexMut<usize> for SinglePrecision {
+    fn index_mut(&mut self, t: usize) -> &mut Self::Output {
+        &mut self.working.columns[t % WORKING]
+    }
+}
+
+impl Columns<f64> for SinglePrecision {
+    fn reset(&mut self, len: usize, initial: [f64; State::COUNT]) {
+        self.working = Working::new(initial);
+        self.columns.clear();
+        self.columns.reserve(len);
+        self.len = len;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn pair_mut(&mut self, t: usize) -> (&[f64; State::COUNT], &mut [f64; State::COUNT]) {
+        self.working.pair_mut(t)
+    }
+
+    fn finish(&mut self, t: usize) {
+        self.columns.push(self[t].map(|score| score as f32));
+        self.working.finish(t);
+    }
+
+    fn get(&self, t: usize, state: State) -> f64 {
+        self.columns[t][state] as f64
+    }
+}
+
+/// Number of traceback entries packed in a word.
+const ENTRIES: usize = 32 / 5;
+
+/// Number of words per packed column.
+const WORDS: usize = State::COUNT.div_ceil(ENTRIES);
+
+/// Traceback entries packed in 5 bits once their column is finished: 0 for
+/// no state, and the index of the state plus one otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct PackedTraceback {
+    working: Working<Option<State>>,
+    columns: Vec<[u32; WORDS]>,
+    len: usize,
+}
+
+impl Index<usize> for PackedTraceback {
+    type Output = [Option<State>; State::COUNT];
+
+    fn index(&self, t: usize) -> &Self::Output {
+        &self.working.columns[t % WORKING]
+    }
+}
+
+impl IndexMut<usize> for PackedTraceback {
+    fn index_mut(&mut self, t: usize) -> &mut Self::Output {
+        &mut self.working.columns[t % WORKING]
+    }
+}
+
+impl Columns<Option<State>> for PackedTraceback {
+    fn reset(&mut self, len: usize, initial: [Option<State>; State::COUNT]) {
+        self.working = Working::new(initial);
+        self.columns.clear();
+        self.columns.reserve(len);
+        self.len = len;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn pair_mut(
+        &mut self,
+        t: usize,
+    ) -> (
+        &[Option<State>; State::COUNT],
+        &mut [Option<State>; State::COUNT],
+    ) {
+        self.working.pair_mut(t)
+    }
+
+    fn finish(&mut self, t: usize) {
+        let mut packed = [0; WORDS];
+        for (i, entry) in self[t].iter().enumerate() {
+            let code = entry.map_or(0, |state| state as u32 + 1);
+            packed[i / ENTRIES] |= code << (5 * (i % ENTRIES));
+        }
+        self.columns.push(packed);
+        self.working.finish(t);
+    }
+
+    fn get(&self, t: usize, state: State) -> Option<State> {
+        let i = state as usize;
+        let code = (self.columns[t][i / ENTRIES] >> (5 * (i % ENTRIES))) & 0b11111;
+        code.checked_sub(1)
+            .and_then(|index| State::iter().nth(index as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_size() {
+        // the bytes per nucleotide given in the README
+        let full = std::mem::size_of::<[f64; State::COUNT]>()
+            + std::mem::size_of::<[Option<State>; State::COUNT]>();
+        let compact =
+            std::mem::size_of::<[f32; State::COUNT]>() + std::mem::size_of::<[u32; WORDS]>();
+        assert_eq!(261, full);
+        assert_eq!(136, compact);
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut scores = SinglePrecision::default();
+        let mut traceback = PackedTraceback::default();
+        let state = |t: usize, i: usize| State::iter().nth((t * 7 + i) % State::COUNT);
+        // the second sequence reuses the columns of the first
+        for len in [20, 11] {
+            scores.reset(len, [0.0; State::COUNT]);
+            traceback.reset(len, [Some(State::S); State::COUNT]);
+            for t in 0..len {
+                if t > 0 {
+                    let (previous, current) = scores.pair_mut(t);
+                    assert_eq!(1.5 * (t - 1) as f64, previous[0]);
+                    current[0] = previous[0] + 1.5;
+                }
+                for (i, entry) in traceback[t].iter_mut().enumerate() {
+                    *entry = if i == t { None } else { state(t, i) };
+                }
+                scores.finish(t);
+                traceback.finish(t);
+            }
+
+            assert_eq!(len, traceback.len());
+            for t in 0..len {
+                assert_eq!(1.5 * t as f64, scores.get(t, State::S));
+                for (i, s) in State::iter().enumerate() {
+                    let expected = if i == t { None } else { state(t, i) };
+                    assert_eq!(expected, traceback.get(t, s));
+                }
+            }
+        }
+    }
+}
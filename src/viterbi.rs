@@ -1,45 +1,452 @@
+extern crate thiserror;
+use thiserror::Error;
+
 use strum::EnumCount;
 use strum::IntoEnumIterator;
 
 use crate::dna::Nuc::{A, C, G, T};
-use crate::dna::{count_cg_content, trinucleotide, Nuc};
+use crate::dna::{count_cg_content, trinucleotide, Nuc, ACGT, BI_ACGT};
+use crate::trellis::{Columns, PackedTraceback, SinglePrecision, Trellis};
 use crate::{gene, hmm};
 
+/// Shortest sequence the model can decode.
+pub const MIN_SEQUENCE_LENGTH: usize = 3;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ReadError {
+    #[error("sequence of {0} nucleotides is shorter than {}", MIN_SEQUENCE_LENGTH)]
+    TooShort(usize),
+    #[error("sequence contains no A, C, G or T")]
+    NoNucleotides,
+}
+
+/// Checks whether a sequence can be decoded.
+pub fn check(seq: &[Nuc]) -> Result<(), ReadError> {
+    if seq.len() < MIN_SEQUENCE_LENGTH {
+        Err(ReadError::TooShort(seq.len()))
+    } else if seq.iter().all(|n| n.to_int().is_none()) {
+        Err(ReadError::NoNucleotides)
+    } else {
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn viterbi(
     global: &hmm::Global,
     locals: &Vec<hmm::Local>,
     head: Vec<u8>,
     seq: Vec<Nuc>,
+    mask: &[bool],
+    whole_genome: bool,
+    filter: &gene::GeneFilter,
+    trellis: Trellis,
+) -> Result<gene::ReadPrediction, ReadError> {
+    viterbi_in(
+        &mut Scratch::default(),
+        global,
+        locals,
+        head,
+        seq,
+        mask,
+        whole_genome,
+        filter,
+        trellis,
+    )
+}
+
+/// Buffers of the decoder that are kept from one sequence to the next, so
+/// that decoding many short reads does not allocate a trellis for each.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    alpha: Vec<[f64; hmm::State::COUNT]>,
+    path: Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+    compact_alpha: SinglePrecision,
+    compact_path: PackedTraceback,
+    vpath: Vec<hmm::State>,
+}
+
+/// Decodes reads one after the other with the buffers of `scratch`, instead
+/// of allocating a trellis for each read. The reads are not decoded in
+/// lockstep: each read goes through the same steps as with `viterbi`.
+pub fn viterbi_reusing(
+    scratch: &mut Scratch,
+    global: &hmm::Global,
+    locals: &Vec<hmm::Local>,
+    reads: Vec<(Vec<u8>, Vec<Nuc>, Vec<bool>)>,
+    whole_genome: bool,
+    filter: &gene::GeneFilter,
+    trellis: Trellis,
+) -> Vec<Result<gene::ReadPrediction, ReadError>> {
+    reads
+        .into_iter()
+        .map(|(head, seq, mask)| {
+            viterbi_in(
+                scratch,
+                global,
+                locals,
+                head,
+                seq,
+                &mask,
+                whole_genome,
+                filter,
+                trellis,
+            )
+        })
+        .collect()
+}
+
+#[allow(clippy::ptr_arg, clippy::too_many_arguments)]
+fn viterbi_in(
+    scratch: &mut Scratch,
+    global: &hmm::Global,
+    locals: &Vec<hmm::Local>,
+    head: Vec<u8>,
+    seq: Vec<Nuc>,
+    mask: &[bool],
     whole_genome: bool,
+    filter: &gene::GeneFilter,
+    trellis: Trellis,
+) -> Result<gene::ReadPrediction, ReadError> {
+    check(&seq)?;
+    let cg = count_cg_content(&seq);
+    let local = &locals[cg];
+    let mut read_prediction = scratch.decode(
+        trellis,
+        global,
+        local,
+        head,
+        seq,
+        mask,
+        whole_genome,
+        whole_genome,
+        filter.min_length,
+    );
+    read_prediction.cg = Some(cg);
+    read_prediction
+        .genes
+        .retain(|gene| filter.accepts(gene, whole_genome));
+    Ok(read_prediction)
+}
+
+impl Scratch {
+    /// Decodes the sequence with the trellis stored as chosen, with
+    /// `whole_genome` for the forward pass and `complete` for the genes.
+    #[allow(clippy::too_many_arguments)]
+    fn decode(
+        &mut self,
+        trellis: Trellis,
+        global: &hmm::Global,
+        local: &hmm::Local,
+        head: Vec<u8>,
+        seq: Vec<Nuc>,
+        mask: &[bool],
+        whole_genome: bool,
+        complete: bool,
+        min_length: usize,
+    ) -> gene::ReadPrediction {
+        let Scratch {
+            alpha,
+            path,
+            compact_alpha,
+            compact_path,
+            vpath,
+        } = self;
+        match trellis {
+            Trellis::Full => decode_in(
+                alpha,
+                path,
+                vpath,
+                global,
+                local,
+                head,
+                seq,
+                mask,
+                whole_genome,
+                complete,
+                min_length,
+            ),
+            Trellis::Compact => decode_in(
+                compact_alpha,
+                compact_path,
+                vpath,
+                global,
+                local,
+                head,
+                seq,
+                mask,
+                whole_genome,
+                complete,
+                min_length,
+            ),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decode_in<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
+    vpath: &mut Vec<hmm::State>,
+    global: &hmm::Global,
+    local: &hmm::Local,
+    head: Vec<u8>,
+    seq: Vec<Nuc>,
+    mask: &[bool],
+    whole_genome: bool,
+    complete: bool,
+    min_length: usize,
+) -> gene::ReadPrediction {
+    forward_into(alpha, path, global, local, &seq, mask, whole_genome);
+    let none_states = backtrack(alpha, path, vpath);
+    let mut read_prediction = build_genes(local, head, seq, complete, min_length, vpath, alpha);
+    read_prediction.none_states = none_states;
+    read_prediction
+}
+
+/// Length of the windows in which long reads are decoded.
+pub const LONG_READ_WINDOW: usize = 100_000;
+/// Overlap between consecutive windows of a long read.
+pub const LONG_READ_OVERLAP: usize = 10_000;
+
+/// Decodes a long read: insertions and deletions are corrected as for short
+/// reads, while start codons are refined and genes are translated as for
+/// complete genomic sequences. Reads longer than `LONG_READ_WINDOW` are
+/// decoded in overlapping windows to bound memory usage; each gene is
+/// reported by the window whose center region contains its start, which is
+/// extended until the gene ends within it.
+pub fn viterbi_long_read(
+    global: &hmm::Global,
+    locals: &Vec<hmm::Local>,
+    head: Vec<u8>,
+    seq: Vec<Nuc>,
+    mask: &[bool],
+    filter: &gene::GeneFilter,
+    trellis: Trellis,
+) -> Result<gene::ReadPrediction, ReadError> {
+    check(&seq)?;
+    Ok(viterbi_windows(
+        global,
+        locals,
+        head,
+        seq,
+        mask,
+        filter,
+        trellis,
+        LONG_READ_WINDOW,
+        LONG_READ_OVERLAP,
+    ))
+}
+
+#[allow(clippy::ptr_arg, clippy::too_many_arguments)]
+fn viterbi_windows(
+    global: &hmm::Global,
+    locals: &Vec<hmm::Local>,
+    head: Vec<u8>,
+    seq: Vec<Nuc>,
+    mask: &[bool],
+    filter: &gene::GeneFilter,
+    trellis: Trellis,
+    window_length: usize,
+    overlap: usize,
 ) -> gene::ReadPrediction {
-    let local = &locals[count_cg_content(&seq)];
-    let (alpha, path) = forward(global, local, &seq, whole_genome);
-    let vpath = backtrack(&alpha, path);
-    build_genes(local, head, seq, whole_genome, vpath, alpha)
+    let cg = count_cg_content(&seq);
+    let local = &locals[cg];
+    let mut scratch = Scratch::default();
+    let mut read_prediction = gene::ReadPrediction::new(head);
+    read_prediction.cg = Some(cg);
+    let mut offset = 0;
+    loop {
+        let mut end = (offset + window_length).min(seq.len());
+        let lo = if offset == 0 { 0 } else { offset + overlap / 2 };
+        let hi = if end == seq.len() {
+            end
+        } else {
+            end - overlap / 2
+        };
+        // A gene starting in the center region may run past the end of the
+        // window, in which case it ends within the last two codons: the
+        // window is extended until the gene ends before them.
+        let part = loop {
+            let window = seq[offset..end].to_vec();
+            let window_mask = mask.get(offset..end).unwrap_or(&[]);
+            let part = scratch.decode(
+                trellis,
+                global,
+                local,
+                vec![],
+                window,
+                window_mask,
+                false,
+                true,
+                filter.min_length,
+            );
+            let cut = end < seq.len()
+                && part.genes.iter().any(|gene| {
+                    lo < gene.start + offset
+                        && gene.start + offset <= hi
+                        && gene.end + offset + 6 > end
+                });
+            if !cut {
+                break part;
+            }
+            end = (end + window_length - overlap).min(seq.len());
+        };
+        read_prediction.none_states += part.none_states;
+
+        for mut gene in part.genes {
+            gene.start += offset;
+            gene.end += offset;
+            gene.frame = (gene.frame - 1 + offset) % 3 + 1;
+            gene.inserted.iter_mut().for_each(|i| *i += offset);
+            gene.deleted.iter_mut().for_each(|d| *d += offset);
+            // the tail of a gene reported by the previous, extended window
+            // may show up again as a gene starting after `lo`
+            let reported = read_prediction
+                .genes
+                .iter()
+                .any(|g| g.end == gene.end && g.forward_strand == gene.forward_strand);
+            if lo < gene.start && gene.start <= hi && !reported && filter.accepts(&gene, true) {
+                read_prediction.genes.push(gene);
+            }
+        }
+
+        if hi == seq.len() {
+            break;
+        }
+        offset = hi - overlap / 2;
+    }
+    read_prediction
+}
+
+/// Fills the trellis of the model for the sequence. The match and
+/// insertion states are updated six lanes at a time with AVX2 vectors when
+/// the processor supports them (see `lanes_supported`), and one by one
+/// otherwise. Both kernels give bit-identical results. Fails if the
+/// sequence can't be decoded, see `check`.
+pub fn forward<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    global: &hmm::Global,
+    local: &hmm::Local,
+    seq: &Vec<Nuc>,
+    mask: &[bool],
+    whole_genome: bool,
+) -> Result<(A, P), ReadError> {
+    check(seq)?;
+    let mut alpha = A::default();
+    let mut path = P::default();
+    forward_into(
+        &mut alpha,
+        &mut path,
+        global,
+        local,
+        seq,
+        mask,
+        whole_genome,
+    );
+    Ok((alpha, path))
 }
 
-pub fn forward(
+/// As `forward`, but always with the scalar kernel, to compare it with the
+/// lanes.
+pub fn forward_scalar<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
     global: &hmm::Global,
     local: &hmm::Local,
     seq: &Vec<Nuc>,
+    mask: &[bool],
+    whole_genome: bool,
+) -> Result<(A, P), ReadError> {
+    check(seq)?;
+    let mut alpha = A::default();
+    let mut path = P::default();
+    forward_kernel::<A, P, false>(
+        &mut alpha,
+        &mut path,
+        global,
+        local,
+        seq,
+        mask,
+        whole_genome,
+    );
+    Ok((alpha, path))
+}
+
+/// Whether `forward` updates the match and insertion states in lanes, which
+/// requires AVX2 on x86-64. Other architectures use the scalar kernel.
+pub fn lanes_supported() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// The forward pass, filling columns that may be reused from an earlier
+/// sequence.
+fn forward_into<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
+    global: &hmm::Global,
+    local: &hmm::Local,
+    seq: &Vec<Nuc>,
+    mask: &[bool],
+    whole_genome: bool,
+) {
+    #[cfg(target_arch = "x86_64")]
+    if lanes_supported() {
+        // SAFETY: the processor supports AVX2.
+        return unsafe { forward_avx2(alpha, path, global, local, seq, mask, whole_genome) };
+    }
+    forward_kernel::<A, P, false>(alpha, path, global, local, seq, mask, whole_genome)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn forward_avx2<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
+    global: &hmm::Global,
+    local: &hmm::Local,
+    seq: &Vec<Nuc>,
+    mask: &[bool],
+    whole_genome: bool,
+) {
+    forward_kernel::<A, P, true>(alpha, path, global, local, seq, mask, whole_genome)
+}
+
+/// The forward pass, with the match and insertion states updated by
+/// `coding_lanes` if `LANES`, and by the scalar transitions otherwise.
+/// `LANES` requires AVX2, so the kernel with lanes only runs through
+/// `forward_avx2`.
+#[inline(always)]
+#[allow(clippy::needless_borrow, clippy::nonminimal_bool)]
+fn forward_kernel<A: Columns<f64>, P: Columns<Option<hmm::State>>, const LANES: bool>(
+    alpha: &mut A,
+    path: &mut P,
+    global: &hmm::Global,
+    local: &hmm::Local,
+    seq: &Vec<Nuc>,
+    mask: &[bool],
     whole_genome: bool,
-) -> (
-    Vec<[f64; hmm::State::COUNT]>,
-    Vec<[Option<hmm::State>; hmm::State::COUNT]>,
 ) {
-    let mut alpha: Vec<[f64; hmm::State::COUNT]> = Vec::with_capacity(seq.len());
-    let mut path: Vec<[Option<hmm::State>; hmm::State::COUNT]> = Vec::with_capacity(seq.len());
+    alpha.reset(seq.len(), [0.0; hmm::State::COUNT]);
+    path.reset(seq.len(), [Some(hmm::State::S); hmm::State::COUNT]);
     let mut temp_i: [usize; hmm::PERIOD] = [0; hmm::PERIOD];
     let mut temp_i_1: [usize; hmm::PERIOD] = [0; hmm::PERIOD];
 
-    for _ in 0..seq.len() {
-        alpha.push([0.0; hmm::State::COUNT]);
-        path.push([Some(hmm::State::S); hmm::State::COUNT]);
-    }
     alpha[0].copy_from_slice(&global.pi);
     for i in &mut alpha[0] {
         *i *= -1.0
     }
+    if mask.first() == Some(&true) {
+        for i in hmm::State::iter() {
+            if i != hmm::State::R {
+                alpha[0][i] = f64::INFINITY;
+            }
+        }
+    }
 
     // If the sequence starts with a stop codon
     if seq[0] == T
@@ -92,8 +499,24 @@ pub fn forward(
         }
     }
 
+    alpha.finish(0);
+    path.finish(0);
+
     let mut num_noncoding = 0; // number of invalid nts in sequence
+    let mut run = 0; // length of the homopolymer run ending at t - 1
     for t in 1..seq.len() {
+        run = if t > 1 && seq[t - 1].to_int().is_some() && seq[t - 1] == seq[t - 2] {
+            run + 1
+        } else {
+            1
+        };
+        // insertions only extend the run if they repeat its nucleotide
+        let indel_d = global.indel(run);
+        let indel_i = if seq[t] == seq[t - 1] {
+            indel_d
+        } else {
+            global.indel(1)
+        };
         let from = (seq[t - 1]).to_int().unwrap_or(2);
         let from0 = if t > 1 {
             (seq[t - 2]).to_int().unwrap_or(2)
@@ -101,207 +524,261 @@ pub fn forward(
             2
         };
         let to = (seq[t]).to_int().unwrap_or_else(|| {
-            num_noncoding += 1;
+            if !seq[t].is_ambiguous() {
+                num_noncoding += 1;
+            }
             2
         });
         let from2 = from0 * 4 + from;
 
-        // M state
-        if alpha[t][hmm::State::M1].is_finite() {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M6, hmm::State::M1, local.e_m[0][from2][to], global.tr.gg);
-            if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5, hmm::State::M1, 2.0, local.e_m[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4, hmm::State::M1, 3.0, local.e_m[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3, hmm::State::M1, 4.0, local.e_m[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2, hmm::State::M1, 5.0, local.e_m[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1, hmm::State::M1, 6.0, local.e_m[0][from2][to]);
+        // ambiguity codes emit the average of their compatible nucleotides
+        let (e_m, e_m1, rr) = if seq[t].is_ambiguous()
+            || seq[t - 1].is_ambiguous()
+            || (t > 1 && seq[t - 2].is_ambiguous())
+        {
+            let bases = |n: Nuc, i: &'static [usize]| if n.is_ambiguous() { n.bases() } else { i };
+            let codes = [
+                if t > 1 {
+                    bases(seq[t - 2], &INDICES[from0..=from0])
+                } else {
+                    &INDICES[2..=2]
+                },
+                bases(seq[t - 1], &INDICES[from..=from]),
+                bases(seq[t], &INDICES[to..=to]),
+            ];
+            (
+                average_emissions(&local.e_m, codes),
+                average_emissions(&local.e_m1, codes),
+                average(codes[1], codes[2], |a, b| local.tr_rr[a][b]),
+            )
+        } else {
+            (
+                local.e_m.map(|e| e[from2][to]),
+                local.e_m1.map(|e| e[from2][to]),
+                local.tr_rr[from][to],
+            )
+        };
+
+        if LANES {
+            // SAFETY: the kernel with lanes is only called by `forward_avx2`,
+            // when the processor supports AVX2.
+            #[cfg(target_arch = "x86_64")]
+            unsafe {
+                coding_lanes(
+                    alpha,
+                    path,
+                    &mut temp_i,
+                    &mut temp_i_1,
+                    global,
+                    &indel_d,
+                    &indel_i,
+                    seq,
+                    t,
+                    from,
+                    to,
+                    &e_m,
+                    &e_m1,
+                    whole_genome,
+                );
+            }
+        } else {
+            // M state
+            if alpha[t][hmm::State::M1].is_finite() {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M6, hmm::State::M1, e_m[0], global.tr.gg);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5, hmm::State::M1, 2.0, e_m[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4, hmm::State::M1, 3.0, e_m[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3, hmm::State::M1, 4.0, e_m[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2, hmm::State::M1, 5.0, e_m[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1, hmm::State::M1, 6.0, e_m[0]);
+                }
+                from_s_to_m(alpha, path, t, e_m[0]);
+                #[rustfmt::skip] from_i_to_m(alpha, path, &seq, temp_i[5], global, t, hmm::State::I6, hmm::State::M1);
             }
-            from_s_to_m(&mut alpha, &mut path, local, t, from2, to);
-            #[rustfmt::skip] from_i_to_m(&mut alpha, &mut path, &seq, temp_i[5], global, t, hmm::State::I6, hmm::State::M1);
-        }
 
-        if alpha[t][hmm::State::M2].is_finite() {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M1, hmm::State::M2, local.e_m[1][from2][to], 0.0);
-            if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6, hmm::State::M2, 2.0, local.e_m[1][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5, hmm::State::M2, 3.0, local.e_m[1][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4, hmm::State::M2, 4.0, local.e_m[1][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3, hmm::State::M2, 5.0, local.e_m[1][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2, hmm::State::M2, 6.0, local.e_m[1][from2][to]);
+            if alpha[t][hmm::State::M2].is_finite() {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M1, hmm::State::M2, e_m[1], 0.0);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6, hmm::State::M2, 2.0, e_m[1]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5, hmm::State::M2, 3.0, e_m[1]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4, hmm::State::M2, 4.0, e_m[1]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3, hmm::State::M2, 5.0, e_m[1]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2, hmm::State::M2, 6.0, e_m[1]);
+                }
+
+                #[rustfmt::skip] from_i_to_m(alpha, path, &seq, temp_i[0], global, t, hmm::State::I1, hmm::State::M2);
             }
 
-            #[rustfmt::skip] from_i_to_m(&mut alpha, &mut path, &seq, temp_i[0], global, t, hmm::State::I1, hmm::State::M2);
-        }
+            if alpha[t][hmm::State::M3].is_finite() {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M2, hmm::State::M3, e_m[2], 0.0);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6, hmm::State::M3, 3.0, e_m[2]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5, hmm::State::M3, 4.0, e_m[2]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4, hmm::State::M3, 5.0, e_m[2]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3, hmm::State::M3, 6.0, e_m[2]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1, hmm::State::M3, 2.0, e_m[2]);
+                }
 
-        if alpha[t][hmm::State::M3].is_finite() {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M2, hmm::State::M3, local.e_m[2][from2][to], 0.0);
-            if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6, hmm::State::M3, 3.0, local.e_m[2][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5, hmm::State::M3, 4.0, local.e_m[2][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4, hmm::State::M3, 5.0, local.e_m[2][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3, hmm::State::M3, 6.0, local.e_m[2][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1, hmm::State::M3, 2.0, local.e_m[2][from2][to]);
+                #[rustfmt::skip] from_i_to_m(alpha, path, &seq, temp_i[1], global, t, hmm::State::I2, hmm::State::M3);
             }
 
-            #[rustfmt::skip] from_i_to_m(&mut alpha, &mut path, &seq, temp_i[1], global, t, hmm::State::I2, hmm::State::M3);
-        }
+            if alpha[t][hmm::State::M4].is_finite() {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M3, hmm::State::M4, e_m[3], 0.0);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6, hmm::State::M4, 4.0, e_m[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5, hmm::State::M4, 5.0, e_m[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4, hmm::State::M4, 6.0, e_m[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2, hmm::State::M4, 2.0, e_m[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1, hmm::State::M4, 3.0, e_m[3]);
+                }
 
-        if alpha[t][hmm::State::M4].is_finite() {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M3, hmm::State::M4, local.e_m[3][from2][to], 0.0);
-            if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6, hmm::State::M4, 4.0, local.e_m[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5, hmm::State::M4, 5.0, local.e_m[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4, hmm::State::M4, 6.0, local.e_m[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2, hmm::State::M4, 2.0, local.e_m[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1, hmm::State::M4, 3.0, local.e_m[3][from2][to]);
+                #[rustfmt::skip] from_i_to_m(alpha, path, &seq, temp_i[2], global, t, hmm::State::I3, hmm::State::M4);
             }
 
-            #[rustfmt::skip] from_i_to_m(&mut alpha, &mut path, &seq, temp_i[2], global, t, hmm::State::I3, hmm::State::M4);
-        }
+            if alpha[t][hmm::State::M5].is_finite() {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M4, hmm::State::M5, e_m[4], 0.0);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6, hmm::State::M5, 5.0, e_m[4]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5, hmm::State::M5, 6.0, e_m[4]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3, hmm::State::M5, 2.0, e_m[4]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2, hmm::State::M5, 3.0, e_m[4]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1, hmm::State::M5, 4.0, e_m[4]);
+                }
 
-        if alpha[t][hmm::State::M5].is_finite() {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M4, hmm::State::M5, local.e_m[4][from2][to], 0.0);
-            if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6, hmm::State::M5, 5.0, local.e_m[4][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5, hmm::State::M5, 6.0, local.e_m[4][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3, hmm::State::M5, 2.0, local.e_m[4][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2, hmm::State::M5, 3.0, local.e_m[4][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1, hmm::State::M5, 4.0, local.e_m[4][from2][to]);
+                #[rustfmt::skip] from_i_to_m(alpha, path, &seq, temp_i[3], global, t, hmm::State::I4, hmm::State::M5);
             }
 
-            #[rustfmt::skip] from_i_to_m(&mut alpha, &mut path, &seq, temp_i[3], global, t, hmm::State::I4, hmm::State::M5);
-        }
+            if alpha[t][hmm::State::M6].is_finite() {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M5, hmm::State::M6, e_m[5], 0.0);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6, hmm::State::M6, 6.0, e_m[5]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4, hmm::State::M6, 2.0, e_m[5]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3, hmm::State::M6, 3.0, e_m[5]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2, hmm::State::M6, 4.0, e_m[5]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1, hmm::State::M6, 5.0, e_m[5]);
+                }
 
-        if alpha[t][hmm::State::M6].is_finite() {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M5, hmm::State::M6, local.e_m[5][from2][to], 0.0);
-            if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6, hmm::State::M6, 6.0, local.e_m[5][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4, hmm::State::M6, 2.0, local.e_m[5][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3, hmm::State::M6, 3.0, local.e_m[5][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2, hmm::State::M6, 4.0, local.e_m[5][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1, hmm::State::M6, 5.0, local.e_m[5][from2][to]);
+                #[rustfmt::skip] from_i_to_m(alpha, path, &seq, temp_i[4], global, t, hmm::State::I5, hmm::State::M6);
             }
 
-            #[rustfmt::skip] from_i_to_m(&mut alpha, &mut path, &seq, temp_i[4], global, t, hmm::State::I5, hmm::State::M6);
-        }
+            // I state
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I1);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I2);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I3);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I4);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I5);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I6);
+            #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i[0], global, &indel_i, t, from, to, hmm::State::M1, hmm::State::I1, 0.0);
+            #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i[1], global, &indel_i, t, from, to, hmm::State::M2, hmm::State::I2, 0.0);
+            #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i[2], global, &indel_i, t, from, to, hmm::State::M3, hmm::State::I3, 0.0);
+            #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i[3], global, &indel_i, t, from, to, hmm::State::M4, hmm::State::I4, 0.0);
+            #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i[4], global, &indel_i, t, from, to, hmm::State::M5, hmm::State::I5, 0.0);
+            #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i[5], global, &indel_i, t, from, to, hmm::State::M6, hmm::State::I6, global.tr.gg);
+
+            // M' state
+            if t >= 3
+                && seq[t - 1] == A
+                && ((seq[t - 2] == T && seq[t - 3] == T)
+                    || (seq[t - 2] == T && seq[t - 3] == C)
+                    || (seq[t - 2] == C && seq[t - 3] == T))
+            {
+                #[rustfmt::skip] from_s_to_m1(alpha, path, t, hmm::State::M1r, e_m1[0]);
+            } else {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M6r, hmm::State::M1r, e_m1[0], global.tr.gg);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5r, hmm::State::M1r, 2.0, e_m1[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4r, hmm::State::M1r, 3.0, e_m1[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3r, hmm::State::M1r, 4.0, e_m1[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2r, hmm::State::M1r, 5.0, e_m1[0]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1r, hmm::State::M1r, 6.0, e_m1[0]);
+                }
+                #[rustfmt::skip] from_i1_to_m1(alpha, path, &seq, temp_i_1[5], global, t, hmm::State::I6r, hmm::State::M1r);
+            }
 
-        // I state
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I1);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I2);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I3);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I4);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I5);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I6);
-        #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i[0], global, t, from, to, hmm::State::M1, hmm::State::I1, 0.0);
-        #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i[1], global, t, from, to, hmm::State::M2, hmm::State::I2, 0.0);
-        #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i[2], global, t, from, to, hmm::State::M3, hmm::State::I3, 0.0);
-        #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i[3], global, t, from, to, hmm::State::M4, hmm::State::I4, 0.0);
-        #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i[4], global, t, from, to, hmm::State::M5, hmm::State::I5, 0.0);
-        #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i[5], global, t, from, to, hmm::State::M6, hmm::State::I6, global.tr.gg);
-
-        // M' state
-        if t >= 3
-            && seq[t - 1] == A
-            && ((seq[t - 2] == T && seq[t - 3] == T)
-                || (seq[t - 2] == T && seq[t - 3] == C)
-                || (seq[t - 2] == C && seq[t - 3] == T))
-        {
-            #[rustfmt::skip] from_s_to_m1(&mut alpha, &mut path, t, hmm::State::M1r, local.e_m1[0][from2][to]);
-        } else {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M6r, hmm::State::M1r, local.e_m1[0][from2][to], global.tr.gg);
+            #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M1r, hmm::State::M2r, e_m1[1], 0.0);
             if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5r, hmm::State::M1r, 2.0, local.e_m1[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4r, hmm::State::M1r, 3.0, local.e_m1[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3r, hmm::State::M1r, 4.0, local.e_m1[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2r, hmm::State::M1r, 5.0, local.e_m1[0][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1r, hmm::State::M1r, 6.0, local.e_m1[0][from2][to]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6r, hmm::State::M2r, 2.0, e_m1[1]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5r, hmm::State::M2r, 3.0, e_m1[1]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4r, hmm::State::M2r, 4.0, e_m1[1]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3r, hmm::State::M2r, 5.0, e_m1[1]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2r, hmm::State::M2r, 6.0, e_m1[1]);
             }
-            #[rustfmt::skip] from_i1_to_m1(&mut alpha, &mut path, &seq, temp_i_1[5], global, t, hmm::State::I6r, hmm::State::M1r);
-        }
+            #[rustfmt::skip] from_i1_to_m1(alpha, path, &seq, temp_i_1[0], global, t, hmm::State::I1r, hmm::State::M2r);
 
-        #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M1r, hmm::State::M2r, local.e_m1[1][from2][to], 0.0);
-        if !whole_genome {
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6r, hmm::State::M2r, 2.0, local.e_m1[1][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5r, hmm::State::M2r, 3.0, local.e_m1[1][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4r, hmm::State::M2r, 4.0, local.e_m1[1][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3r, hmm::State::M2r, 5.0, local.e_m1[1][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2r, hmm::State::M2r, 6.0, local.e_m1[1][from2][to]);
-        }
-        #[rustfmt::skip] from_i1_to_m1(&mut alpha, &mut path, &seq, temp_i_1[0], global, t, hmm::State::I1r, hmm::State::M2r);
-
-        #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M2r, hmm::State::M3r, local.e_m1[2][from2][to], 0.0);
-        if !whole_genome {
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6r, hmm::State::M3r, 3.0, local.e_m1[2][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5r, hmm::State::M3r, 4.0, local.e_m1[2][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4r, hmm::State::M3r, 5.0, local.e_m1[2][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3r, hmm::State::M3r, 6.0, local.e_m1[2][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1r, hmm::State::M3r, 2.0, local.e_m1[2][from2][to]);
-        }
-        #[rustfmt::skip] from_i1_to_m1(&mut alpha, &mut path, &seq, temp_i_1[1], global, t, hmm::State::I2r, hmm::State::M3r);
+            #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M2r, hmm::State::M3r, e_m1[2], 0.0);
+            if !whole_genome {
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6r, hmm::State::M3r, 3.0, e_m1[2]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5r, hmm::State::M3r, 4.0, e_m1[2]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4r, hmm::State::M3r, 5.0, e_m1[2]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3r, hmm::State::M3r, 6.0, e_m1[2]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1r, hmm::State::M3r, 2.0, e_m1[2]);
+            }
+            #[rustfmt::skip] from_i1_to_m1(alpha, path, &seq, temp_i_1[1], global, t, hmm::State::I2r, hmm::State::M3r);
 
-        if t >= 3
-            && seq[t - 1] == A
-            && ((seq[t - 2] == T && seq[t - 3] == T)
-                || (seq[t - 2] == T && seq[t - 3] == C)
-                || (seq[t - 2] == C && seq[t - 3] == T))
-        {
-            #[rustfmt::skip] from_s_to_m1(&mut alpha, &mut path, t, hmm::State::M4r, local.e_m1[3][from2][to]);
-        } else {
-            #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M3r, hmm::State::M4r, local.e_m1[3][from2][to], 0.0);
+            if t >= 3
+                && seq[t - 1] == A
+                && ((seq[t - 2] == T && seq[t - 3] == T)
+                    || (seq[t - 2] == T && seq[t - 3] == C)
+                    || (seq[t - 2] == C && seq[t - 3] == T))
+            {
+                #[rustfmt::skip] from_s_to_m1(alpha, path, t, hmm::State::M4r, e_m1[3]);
+            } else {
+                #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M3r, hmm::State::M4r, e_m1[3], 0.0);
+                if !whole_genome {
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6r, hmm::State::M4r, 4.0, e_m1[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5r, hmm::State::M4r, 5.0, e_m1[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4r, hmm::State::M4r, 6.0, e_m1[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2r, hmm::State::M4r, 2.0, e_m1[3]);
+                    #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1r, hmm::State::M4r, 3.0, e_m1[3]);
+                }
+                #[rustfmt::skip] from_i1_to_m1(alpha, path, &seq, temp_i_1[2], global, t, hmm::State::I3r, hmm::State::M4r);
+            }
+
+            #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M4r, hmm::State::M5r, e_m1[4], 0.0);
             if !whole_genome {
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6r, hmm::State::M4r, 4.0, local.e_m1[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5r, hmm::State::M4r, 5.0, local.e_m1[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4r, hmm::State::M4r, 6.0, local.e_m1[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2r, hmm::State::M4r, 2.0, local.e_m1[3][from2][to]);
-                #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1r, hmm::State::M4r, 3.0, local.e_m1[3][from2][to]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6r, hmm::State::M5r, 5.0, e_m1[4]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M5r, hmm::State::M5r, 6.0, e_m1[4]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3r, hmm::State::M5r, 2.0, e_m1[4]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2r, hmm::State::M5r, 3.0, e_m1[4]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1r, hmm::State::M5r, 4.0, e_m1[4]);
             }
-            #[rustfmt::skip] from_i1_to_m1(&mut alpha, &mut path, &seq, temp_i_1[2], global, t, hmm::State::I3r, hmm::State::M4r);
-        }
+            #[rustfmt::skip] from_i1_to_m1(alpha, path, &seq, temp_i_1[3], global, t, hmm::State::I4r, hmm::State::M5r);
 
-        #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M4r, hmm::State::M5r, local.e_m1[4][from2][to], 0.0);
-        if !whole_genome {
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6r, hmm::State::M5r, 5.0, local.e_m1[4][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M5r, hmm::State::M5r, 6.0, local.e_m1[4][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3r, hmm::State::M5r, 2.0, local.e_m1[4][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2r, hmm::State::M5r, 3.0, local.e_m1[4][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1r, hmm::State::M5r, 4.0, local.e_m1[4][from2][to]);
-        }
-        #[rustfmt::skip] from_i1_to_m1(&mut alpha, &mut path, &seq, temp_i_1[3], global, t, hmm::State::I4r, hmm::State::M5r);
-
-        #[rustfmt::skip] from_m_to_m(&mut alpha, &mut path, global, t, hmm::State::M5r, hmm::State::M6r, local.e_m1[5][from2][to], 0.0);
-        if !whole_genome {
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M6r, hmm::State::M6r, 6.0, local.e_m1[5][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M4r, hmm::State::M6r, 2.0, local.e_m1[5][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M3r, hmm::State::M6r, 3.0, local.e_m1[5][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M2r, hmm::State::M6r, 4.0, local.e_m1[5][from2][to]);
-            #[rustfmt::skip] from_d_to_m(&mut alpha, &mut path, global, t, hmm::State::M1r, hmm::State::M6r, 5.0, local.e_m1[5][from2][to]);
-        }
-        #[rustfmt::skip] from_i1_to_m1(&mut alpha, &mut path, &seq, temp_i_1[4], global, t, hmm::State::I5r, hmm::State::M6r);
-
-        // I' state
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I1r);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I2r);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I3r);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I4r);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I5r);
-        from_i_to_i(&mut alpha, &mut path, global, t, from, to, hmm::State::I6r);
-
-        if (t >= 3 && path[t - 3][hmm::State::Sr] != Some(hmm::State::R))
-            && (t >= 4 && path[t - 4][hmm::State::Sr] != Some(hmm::State::R))
-            && (t >= 5 && path[t - 5][hmm::State::Sr] != Some(hmm::State::R))
-        {
-            #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i_1[0], global, t, from, to, hmm::State::M1r, hmm::State::I1r, 0.0);
-            #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i_1[1], global, t, from, to, hmm::State::M2r, hmm::State::I2r, 0.0);
-            #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i_1[2], global, t, from, to, hmm::State::M3r, hmm::State::I3r, 0.0);
-            #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i_1[3], global, t, from, to, hmm::State::M4r, hmm::State::I4r, 0.0);
-            #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i_1[4], global, t, from, to, hmm::State::M5r, hmm::State::I5r, 0.0);
-            #[rustfmt::skip] from_m_to_i(&mut alpha, &mut path, &mut temp_i_1[5], global, t, from, to, hmm::State::M6r, hmm::State::I6r, global.tr.gg);
+            #[rustfmt::skip] from_m_to_m(alpha, path, global, t, hmm::State::M5r, hmm::State::M6r, e_m1[5], 0.0);
+            if !whole_genome {
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M6r, hmm::State::M6r, 6.0, e_m1[5]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M4r, hmm::State::M6r, 2.0, e_m1[5]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M3r, hmm::State::M6r, 3.0, e_m1[5]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M2r, hmm::State::M6r, 4.0, e_m1[5]);
+                #[rustfmt::skip] from_d_to_m(alpha, path, global, &indel_d, t, hmm::State::M1r, hmm::State::M6r, 5.0, e_m1[5]);
+            }
+            #[rustfmt::skip] from_i1_to_m1(alpha, path, &seq, temp_i_1[4], global, t, hmm::State::I5r, hmm::State::M6r);
+
+            // I' state
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I1r);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I2r);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I3r);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I4r);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I5r);
+            #[rustfmt::skip] from_i_to_i(alpha, path, global, &indel_i, t, from, to, hmm::State::I6r);
+
+            if (t >= 3 && path[t - 3][hmm::State::Sr] != Some(hmm::State::R))
+                && (t >= 4 && path[t - 4][hmm::State::Sr] != Some(hmm::State::R))
+                && (t >= 5 && path[t - 5][hmm::State::Sr] != Some(hmm::State::R))
+            {
+                #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i_1[0], global, &indel_i, t, from, to, hmm::State::M1r, hmm::State::I1r, 0.0);
+                #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i_1[1], global, &indel_i, t, from, to, hmm::State::M2r, hmm::State::I2r, 0.0);
+                #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i_1[2], global, &indel_i, t, from, to, hmm::State::M3r, hmm::State::I3r, 0.0);
+                #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i_1[3], global, &indel_i, t, from, to, hmm::State::M4r, hmm::State::I4r, 0.0);
+                #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i_1[4], global, &indel_i, t, from, to, hmm::State::M5r, hmm::State::I5r, 0.0);
+                #[rustfmt::skip] from_m_to_i(alpha, path, &mut temp_i_1[5], global, &indel_i, t, from, to, hmm::State::M6r, hmm::State::I6r, global.tr.gg);
+            }
         }
 
         // non_coding state
-        from_r_to_r(&mut alpha, &mut path, global, local, t, from, to);
-        from_e_to_r(&mut alpha, &mut path, global, t, hmm::State::E);
-        from_e_to_r(&mut alpha, &mut path, global, t, hmm::State::Er);
+        from_r_to_r(alpha, path, global, t, rr);
+        from_e_to_r(alpha, path, global, t, hmm::State::E);
+        from_e_to_r(alpha, path, global, t, hmm::State::Er);
 
         // end state
         if alpha[t][hmm::State::E] == 0.0 {
@@ -521,7 +998,7 @@ pub fn forward(
             }
         }
 
-        if num_noncoding > 9 {
+        if num_noncoding > 9 || mask.get(t) == Some(&true) {
             for i in hmm::State::iter() {
                 if i != hmm::State::R {
                     alpha[t][i] = f64::INFINITY;
@@ -529,53 +1006,56 @@ pub fn forward(
                 }
             }
         }
-    }
 
-    (alpha, path)
+        alpha.finish(t);
+        path.finish(t);
+    }
 }
 
-fn backtrack(
-    alpha: &Vec<[f64; hmm::State::COUNT]>,
-    path: Vec<[Option<hmm::State>; hmm::State::COUNT]>,
-) -> Vec<hmm::State> {
+/// Fills `vpath` with the most likely path through the trellis, and returns
+/// the number of positions without a state, which are set to non-coding.
+pub fn backtrack<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &A,
+    path: &P,
+    vpath: &mut Vec<hmm::State>,
+) -> usize {
     // backtrack array to find the optimal path
-    let mut vpath: Vec<hmm::State> = Vec::with_capacity(path.len());
+    vpath.clear();
+    vpath.reserve(path.len());
     vpath.push(hmm::State::S); // or null
     let mut prob = f64::INFINITY;
-    for (&prob_, i) in alpha
-        .last()
-        .expect("empty seq")
-        .iter()
-        .zip(hmm::State::iter())
-    {
+    let last = alpha.len().checked_sub(1).expect("empty seq");
+    for i in hmm::State::iter() {
+        let prob_ = alpha.get(last, i);
         if prob_ < prob {
             vpath[0] = i;
             prob = prob_;
         }
     }
 
-    // backtrack the optimal path
+    // backtrack the optimal path, replacing None-states with the non-coding state
+    let mut none_states = 0;
     for t in (0..=path.len() - 2).rev() {
-        vpath.push(path[t + 1][*vpath.last().unwrap()].unwrap_or_else(|| {
-            eprintln!(
-                "Warning: encountered None-state in chosen path, replacing with non-coding state"
-            );
+        vpath.push(path.get(t + 1, *vpath.last().unwrap()).unwrap_or_else(|| {
+            none_states += 1;
             hmm::State::R
         }));
     }
     vpath.reverse();
-    vpath
+    none_states
 }
 
-fn build_genes(
+/// Collects the genes along the most likely path `vpath` of the sequence,
+/// with start codons refined and complete genes reported if `whole_genome`.
+pub fn build_genes<A: Columns<f64>>(
     local: &hmm::Local,
     head: Vec<u8>,
     seq: Vec<Nuc>,
     whole_genome: bool,
-    vpath: Vec<hmm::State>,
-    alpha: Vec<[f64; hmm::State::COUNT]>,
+    min_length: usize,
+    vpath: &[hmm::State],
+    alpha: &A,
 ) -> gene::ReadPrediction {
-    let gene_len = if whole_genome { 120 } else { 60 }; // minimum length to be output
     let mut read_prediction = gene::ReadPrediction::new(head);
     let mut codon_start = 0; // ternaire boolean?
     let mut start_t: isize = -1;
@@ -617,11 +1097,9 @@ fn build_genes(
             dna.push(seq[t]);
             dna_start_t_withstop = t + 1;
             dna_start_t = t + 1;
-            if vpath[t] == hmm::State::M1r || vpath[t] == hmm::State::M4r {
-                if t > 2 {
-                    dna_start_t_withstop = t - 2;
-                    dna.splice(0..0, seq[t - 3..t].iter().cloned());
-                }
+            if (vpath[t] == hmm::State::M1r || vpath[t] == hmm::State::M4r) && t > 2 {
+                dna_start_t_withstop = t - 2;
+                dna.splice(0..0, seq[t - 3..t].iter().cloned());
             }
 
             start_orf = t + 1;
@@ -648,15 +1126,19 @@ fn build_genes(
             }
 
             if dna.len()
-                > gene_len
+                >= min_length
                     + if whole_genome && codon_start == -1 {
                         3
                     } else {
                         0
                     }
+                // the score is averaged over the gene without its first and
+                // last codon, so shorter genes (only possible with a minimum
+                // length below 6) are not reported
+                && end_t > start_t as usize + 5
             {
-                let final_score = (alpha[end_t - 4][vpath[end_t - 4]]
-                    - alpha[start_t as usize + 2][vpath[start_t as usize + 2]])
+                let final_score = (alpha.get(end_t - 4, vpath[end_t - 4])
+                    - alpha.get(start_t as usize + 2, vpath[start_t as usize + 2]))
                     / (end_t - start_t as usize - 5) as f64;
                 let mut frame = start_orf % 3;
                 if frame == 0 {
@@ -677,7 +1159,11 @@ fn build_genes(
                         // find the optimal start codon within 30bp up- and downstream of start codon
                         let mut e_save = 0.0;
                         let mut s_save = 0;
-                        while !(codon == [T, A, A] || codon == [T, A, G] || codon == [T, G, A])
+                        // the window of 30bp around the start codon should fit in
+                        // the sequence, which is only a concern for short genes
+                        let fits = start_old + 32 <= seq.len();
+                        while fits
+                            && !(codon == [T, A, A] || codon == [T, A, G] || codon == [T, G, A])
                             && start_old >= 1 + s + 35
                         {
                             if codon == [A, T, G] || codon == [G, T, G] || codon == [T, T, G] {
@@ -715,7 +1201,7 @@ fn build_genes(
                     read_prediction.genes.push(gene::Gene {
                         start: dna_start_t,
                         end: end_t,
-                        frame: frame,
+                        frame,
                         score: final_score,
                         dna: dna.clone(),
                         forward_strand: true,
@@ -732,7 +1218,11 @@ fn build_genes(
                         // find the optimal start codon within 30bp up- and downstream of start codon
                         let mut e_save = 0.0;
                         let mut s_save = 0;
-                        while !(codon == [T, T, A] || codon == [C, T, A] || codon == [T, C, A])
+                        // the window of 30bp around the start codon should fit in
+                        // the sequence, which is only a concern for short genes
+                        let fits = end_old >= 33;
+                        while fits
+                            && !(codon == [T, T, A] || codon == [C, T, A] || codon == [T, C, A])
                             && end_old - 2 + s + 35 < seq.len()
                         {
                             if codon == [C, A, T] || codon == [C, A, C] || codon == [C, A, A] {
@@ -763,7 +1253,7 @@ fn build_genes(
                     read_prediction.genes.push(gene::Gene {
                         start: dna_start_t_withstop,
                         end: end_t,
-                        frame: frame,
+                        frame,
                         score: final_score,
                         dna: dna.clone(),
                         forward_strand: false,
@@ -815,10 +1305,37 @@ fn build_genes(
     read_prediction
 }
 
+const INDICES: [usize; ACGT] = [0, 1, 2, 3];
+
+/// The log of the average probability of `f` over all combinations of `from` and `to`.
+fn average<F: Fn(usize, usize) -> f64>(from: &[usize], to: &[usize], f: F) -> f64 {
+    let mut sum = 0.0;
+    for &a in from {
+        for &b in to {
+            sum += f(a, b).exp();
+        }
+    }
+    (sum / (from.len() * to.len()) as f64).ln()
+}
+
+fn average_emissions(
+    e: &[[[f64; ACGT]; BI_ACGT]; hmm::PERIOD],
+    codes: [&[usize]; 3],
+) -> [f64; hmm::PERIOD] {
+    e.map(|e| {
+        let mut sum = 0.0;
+        for &a in codes[0] {
+            sum += (average(codes[1], codes[2], |b, c| e[a * ACGT + b][c])).exp();
+        }
+        (sum / codes[0].len() as f64).ln()
+    })
+}
+
 #[inline]
-fn from_m_to_m(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+#[allow(clippy::too_many_arguments)]
+fn from_m_to_m<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     global: &hmm::Global,
     t: usize,
     from_m: hmm::State,
@@ -831,22 +1348,24 @@ fn from_m_to_m(
 }
 
 #[inline]
-fn from_d_to_m(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+#[allow(clippy::too_many_arguments)]
+fn from_d_to_m<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     global: &hmm::Global,
+    indel: &hmm::Indel,
     t: usize,
     from_m: hmm::State,
     to_m: hmm::State,
     num_d: f64,
     emission: f64,
 ) {
-    if num_d > 0.0 {
+    if num_d > 0.0 && num_d <= (global.max_deletion_length + 1) as f64 {
         let temp_alpha = alpha[t - 1][from_m]
-            - global.tr.md
+            - indel.md
             - emission
-            - 0.25_f64.ln() * (num_d - 1.0)
-            - global.tr.dd * (num_d - 2.0)
+            - global.deleted_nucleotide * (num_d - 1.0)
+            - indel.dd * (num_d - 2.0)
             - global.tr.dm;
         if temp_alpha < alpha[t][to_m] {
             alpha[t][to_m] = temp_alpha;
@@ -856,15 +1375,13 @@ fn from_d_to_m(
 }
 
 #[inline]
-fn from_s_to_m(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
-    local: &hmm::Local,
+fn from_s_to_m<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     t: usize,
-    from2: usize,
-    to: usize,
+    emission: f64,
 ) {
-    let temp_alpha = alpha[t - 1][hmm::State::S] - local.e_m[0][from2][to];
+    let temp_alpha = alpha[t - 1][hmm::State::S] - emission;
     if temp_alpha <= alpha[t][hmm::State::M1] {
         alpha[t][hmm::State::M1] = temp_alpha;
         path[t][hmm::State::M1] = Some(hmm::State::S);
@@ -872,9 +1389,9 @@ fn from_s_to_m(
 }
 
 #[inline]
-fn from_s_to_m1(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+fn from_s_to_m1<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     t: usize,
     to_m: hmm::State,
     emission: f64,
@@ -885,9 +1402,10 @@ fn from_s_to_m1(
 }
 
 #[inline]
-fn from_i_to_m(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+#[allow(clippy::too_many_arguments)]
+fn from_i_to_m<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     seq: &Vec<Nuc>,
     temp_i: usize,
     global: &hmm::Global,
@@ -895,8 +1413,22 @@ fn from_i_to_m(
     from_i: hmm::State,
     to_m: hmm::State,
 ) {
-    // to avoid stop codon
+    if forward_insertion_allowed(seq, temp_i, t, to_m) {
+        let temp_alpha = alpha[t - 1][from_i] - global.tr.im - 0.25_f64.ln();
+        if temp_alpha < alpha[t][to_m] {
+            alpha[t][to_m] = temp_alpha;
+            path[t][to_m] = Some(from_i);
+        }
+    }
+}
+
+/// Whether the insertion ending at `temp_i` may be left for `to_m` at `t`
+/// without forming a stop codon.
+#[inline]
+#[allow(clippy::if_same_then_else, clippy::nonminimal_bool, clippy::ptr_arg)]
+fn forward_insertion_allowed(seq: &Vec<Nuc>, temp_i: usize, t: usize, to_m: hmm::State) -> bool {
     if t < 2 {
+        false
     } else if (to_m == hmm::State::M2 || to_m == hmm::State::M5)
         && t + 1 < seq.len()
         && seq[temp_i] == T
@@ -904,42 +1436,66 @@ fn from_i_to_m(
             || (seq[t] == A && seq[t + 1] == G)
             || (seq[t] == G && seq[t + 1] == A))
     {
-    } else if (to_m == hmm::State::M3 || to_m == hmm::State::M6)
-        && temp_i > 0
-        && (seq[temp_i - 1] == T)
-        && ((seq[temp_i] == A && seq[t] == A)
-            || (seq[temp_i] == A && seq[t] == G)
-            || (seq[temp_i] == G && seq[t] == A))
+        false
+    } else {
+        !((to_m == hmm::State::M3 || to_m == hmm::State::M6)
+            && temp_i > 0
+            && (seq[temp_i - 1] == T)
+            && ((seq[temp_i] == A && seq[t] == A)
+                || (seq[temp_i] == A && seq[t] == G)
+                || (seq[temp_i] == G && seq[t] == A)))
+    }
+}
+
+/// Whether the insertion ending at `temp_i_1` may be left for `to_m` at `t`
+/// without forming a stop codon on the reverse strand.
+#[inline]
+#[allow(clippy::if_same_then_else, clippy::nonminimal_bool, clippy::ptr_arg)]
+fn reverse_insertion_allowed(seq: &Vec<Nuc>, temp_i_1: usize, t: usize, to_m: hmm::State) -> bool {
+    if t < 2 {
+        false
+    } else if (to_m == hmm::State::M2r || to_m == hmm::State::M5r)
+        && t + 1 < seq.len()
+        && seq[t + 1] == A
+        && ((seq[t] == T && seq[temp_i_1] == T)
+            || (seq[t] == T && seq[temp_i_1] == C)
+            || (seq[t] == A && seq[temp_i_1] == T))
     {
+        false
     } else {
-        let temp_alpha = alpha[t - 1][from_i] - global.tr.im - 0.25_f64.ln();
-        if temp_alpha < alpha[t][to_m] {
-            alpha[t][to_m] = temp_alpha;
-            path[t][to_m] = Some(from_i);
-        }
+        !((to_m == hmm::State::M3r || to_m == hmm::State::M6r)
+            && seq[t] == A
+            && temp_i_1 > 1
+            && ((seq[temp_i_1] == T && seq[temp_i_1 - 1] == T)
+                || (seq[temp_i_1] == T && seq[temp_i_1 - 1] == C)
+                || (seq[temp_i_1] == C && seq[temp_i_1 - 1] == T)))
     }
 }
 
 #[inline]
-fn from_i_to_i(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+#[allow(clippy::too_many_arguments)]
+fn from_i_to_i<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     global: &hmm::Global,
+    indel: &hmm::Indel,
     t: usize,
     from: usize,
     to: usize,
     i: hmm::State,
 ) {
-    alpha[t][i] = alpha[t - 1][i] - global.tr.ii - global.tr_ii[from][to];
+    alpha[t][i] = alpha[t - 1][i] - indel.ii - global.tr_ii[from][to];
     path[t][i] = Some(i);
 }
 
 #[inline]
-fn from_m_to_i(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+#[allow(clippy::too_many_arguments)]
+fn from_m_to_i<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     temp_i: &mut usize,
     global: &hmm::Global,
+    indel: &hmm::Indel,
     t: usize,
     from: usize,
     to: usize,
@@ -947,7 +1503,7 @@ fn from_m_to_i(
     to_i: hmm::State,
     last_i: f64,
 ) {
-    let temp_alpha = alpha[t - 1][from_m] - global.tr.mi - global.tr_mi[from][to] - last_i;
+    let temp_alpha = alpha[t - 1][from_m] - indel.mi - global.tr_mi[from][to] - last_i;
     if temp_alpha < alpha[t][to_i] {
         alpha[t][to_i] = temp_alpha;
         path[t][to_i] = Some(from_m);
@@ -956,9 +1512,10 @@ fn from_m_to_i(
 }
 
 #[inline]
-fn from_i1_to_m1(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+#[allow(clippy::too_many_arguments)]
+fn from_i1_to_m1<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     seq: &Vec<Nuc>,
     temp_i_1: usize,
     global: &hmm::Global,
@@ -966,23 +1523,7 @@ fn from_i1_to_m1(
     from_i: hmm::State,
     to_m: hmm::State,
 ) {
-    // to avoid stop codon
-    if t < 2 {
-    } else if (to_m == hmm::State::M2r || to_m == hmm::State::M5r)
-        && t + 1 < seq.len()
-        && seq[t + 1] == A
-        && ((seq[t] == T && seq[temp_i_1] == T)
-            || (seq[t] == T && seq[temp_i_1] == C)
-            || (seq[t] == A && seq[temp_i_1] == T))
-    {
-    } else if (to_m == hmm::State::M3r || to_m == hmm::State::M6r)
-        && seq[t] == A
-        && temp_i_1 > 1
-        && ((seq[temp_i_1] == T && seq[temp_i_1 - 1] == T)
-            || (seq[temp_i_1] == T && seq[temp_i_1 - 1] == C)
-            || (seq[temp_i_1] == C && seq[temp_i_1 - 1] == T))
-    {
-    } else {
+    if reverse_insertion_allowed(seq, temp_i_1, t, to_m) {
         let temp_alpha = alpha[t - 1][from_i] - global.tr.im - 0.25_f64.ln();
         if temp_alpha < alpha[t][to_m] {
             alpha[t][to_m] = temp_alpha;
@@ -992,24 +1533,21 @@ fn from_i1_to_m1(
 }
 
 #[inline]
-fn from_r_to_r(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+fn from_r_to_r<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     global: &hmm::Global,
-    local: &hmm::Local,
     t: usize,
-    from: usize,
-    to: usize,
+    rr: f64,
 ) {
-    alpha[t][hmm::State::R] =
-        alpha[t - 1][hmm::State::R] - local.tr_rr[from][to] - global.tr.rr - 0.95_f64.ln();
+    alpha[t][hmm::State::R] = alpha[t - 1][hmm::State::R] - rr - global.tr.rr - 0.95_f64.ln();
     path[t][hmm::State::R] = Some(hmm::State::R);
 }
 
 #[inline]
-fn from_e_to_r(
-    alpha: &mut Vec<[f64; hmm::State::COUNT]>,
-    path: &mut Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+fn from_e_to_r<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
     global: &hmm::Global,
     t: usize,
     from_e: hmm::State,
@@ -1021,6 +1559,454 @@ fn from_e_to_r(
     }
 }
 
+/// Scores of the six match or insertion states of a strand.
+#[cfg(target_arch = "x86_64")]
+type Lanes = [f64; hmm::PERIOD];
+
+#[cfg(target_arch = "x86_64")]
+const MATCH: [hmm::State; hmm::PERIOD] = [
+    hmm::State::M1,
+    hmm::State::M2,
+    hmm::State::M3,
+    hmm::State::M4,
+    hmm::State::M5,
+    hmm::State::M6,
+];
+#[cfg(target_arch = "x86_64")]
+const MATCH_REVERSE: [hmm::State; hmm::PERIOD] = [
+    hmm::State::M1r,
+    hmm::State::M2r,
+    hmm::State::M3r,
+    hmm::State::M4r,
+    hmm::State::M5r,
+    hmm::State::M6r,
+];
+#[cfg(target_arch = "x86_64")]
+const INSERTION: [hmm::State; hmm::PERIOD] = [
+    hmm::State::I1,
+    hmm::State::I2,
+    hmm::State::I3,
+    hmm::State::I4,
+    hmm::State::I5,
+    hmm::State::I6,
+];
+#[cfg(target_arch = "x86_64")]
+const INSERTION_REVERSE: [hmm::State; hmm::PERIOD] = [
+    hmm::State::I1r,
+    hmm::State::I2r,
+    hmm::State::I3r,
+    hmm::State::I4r,
+    hmm::State::I5r,
+    hmm::State::I6r,
+];
+
+/// Number of nucleotides spanned by the deletions into each match state,
+/// in the order in which `from_d_to_m` is applied, as ties keep the first.
+#[cfg(target_arch = "x86_64")]
+const DELETIONS: [[usize; hmm::PERIOD - 1]; hmm::PERIOD] = [
+    [2, 3, 4, 5, 6],
+    [2, 3, 4, 5, 6],
+    [3, 4, 5, 6, 2],
+    [4, 5, 6, 2, 3],
+    [5, 6, 2, 3, 4],
+    [6, 2, 3, 4, 5],
+];
+
+/// The lane of the state before each lane, as from `M6` to `M1`.
+#[cfg(target_arch = "x86_64")]
+const PREVIOUS: [usize; hmm::PERIOD] = [5, 0, 1, 2, 3, 4];
+
+/// Six `f64` lanes, in an AVX vector of four and an SSE2 vector of two.
+/// The operations are those of the scalar transitions, lane by lane, so
+/// the results are bit-identical.
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    #[derive(Clone, Copy)]
+    pub(super) struct F64x6(__m256d, __m128d);
+
+    impl F64x6 {
+        /// The first six values.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn load(values: &[f64]) -> Self {
+            let values = &values[..6];
+            F64x6(
+                _mm256_loadu_pd(values.as_ptr()),
+                _mm_loadu_pd(values.as_ptr().add(4)),
+            )
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn new(lanes: [f64; 6]) -> Self {
+            F64x6(
+                _mm256_setr_pd(lanes[0], lanes[1], lanes[2], lanes[3]),
+                _mm_setr_pd(lanes[4], lanes[5]),
+            )
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn splat(value: f64) -> Self {
+            F64x6(_mm256_set1_pd(value), _mm_set1_pd(value))
+        }
+
+        /// All bits set in the lanes that are `true`, as `lt` gives.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn mask(lanes: &[bool; 6]) -> Self {
+            let m = |k: usize| -(lanes[k] as i64);
+            F64x6(
+                _mm256_castsi256_pd(_mm256_setr_epi64x(m(0), m(1), m(2), m(3))),
+                _mm_castsi128_pd(_mm_set_epi64x(m(5), m(4))),
+            )
+        }
+
+        /// Writes the lanes to the first six values.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn store(self, values: &mut [f64]) {
+            let values = &mut values[..6];
+            _mm256_storeu_pd(values.as_mut_ptr(), self.0);
+            _mm_storeu_pd(values.as_mut_ptr().add(4), self.1);
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn first(self) -> f64 {
+            _mm256_cvtsd_f64(self.0)
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn sub(self, other: Self) -> Self {
+            F64x6(_mm256_sub_pd(self.0, other.0), _mm_sub_pd(self.1, other.1))
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn mul(self, other: Self) -> Self {
+            F64x6(_mm256_mul_pd(self.0, other.0), _mm_mul_pd(self.1, other.1))
+        }
+
+        /// The lanes where `self < other`, which is false for NaN.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn lt(self, other: Self) -> Self {
+            F64x6(
+                _mm256_cmp_pd::<_CMP_LT_OQ>(self.0, other.0),
+                _mm_cmplt_pd(self.1, other.1),
+            )
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn le(self, other: Self) -> Self {
+            F64x6(
+                _mm256_cmp_pd::<_CMP_LE_OQ>(self.0, other.0),
+                _mm_cmple_pd(self.1, other.1),
+            )
+        }
+
+        /// The lanes that are neither infinite nor NaN, as `f64::is_finite`.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn finite(self) -> Self {
+            F64x6(
+                _mm256_cmp_pd::<_CMP_EQ_OQ>(_mm256_sub_pd(self.0, self.0), _mm256_setzero_pd()),
+                _mm_cmpeq_pd(_mm_sub_pd(self.1, self.1), _mm_setzero_pd()),
+            )
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn and(self, other: Self) -> Self {
+            F64x6(_mm256_and_pd(self.0, other.0), _mm_and_pd(self.1, other.1))
+        }
+
+        /// The lanes of `other` where `mask` is set, and of `self` elsewhere.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn select(self, other: Self, mask: Self) -> Self {
+            F64x6(
+                _mm256_blendv_pd(self.0, other.0, mask.0),
+                _mm_blendv_pd(self.1, other.1, mask.1),
+            )
+        }
+
+        /// Whether each lane of a mask is set.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        pub(super) unsafe fn bits(self) -> [bool; 6] {
+            let bits = _mm256_movemask_pd(self.0) | _mm_movemask_pd(self.1) << 4;
+            std::array::from_fn(|k| bits >> k & 1 == 1)
+        }
+    }
+}
+
+/// The match and insertion states at `t`, computed with the same operations
+/// in the same order as the scalar transitions, but for the six states of a
+/// strand at once. The states of a strand are consecutive in a column, so
+/// they are loaded and stored as vectors.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+#[allow(clippy::nonminimal_bool, clippy::too_many_arguments)]
+unsafe fn coding_lanes<A: Columns<f64>, P: Columns<Option<hmm::State>>>(
+    alpha: &mut A,
+    path: &mut P,
+    temp_i: &mut [usize; hmm::PERIOD],
+    temp_i_1: &mut [usize; hmm::PERIOD],
+    global: &hmm::Global,
+    indel_d: &hmm::Indel,
+    indel_i: &hmm::Indel,
+    seq: &Vec<Nuc>,
+    t: usize,
+    from: usize,
+    to: usize,
+    e_m: &Lanes,
+    e_m1: &Lanes,
+    whole_genome: bool,
+) {
+    use avx2::F64x6;
+
+    let reverse_insertions = (t >= 3 && path[t - 3][hmm::State::Sr] != Some(hmm::State::R))
+        && (t >= 4 && path[t - 4][hmm::State::Sr] != Some(hmm::State::R))
+        && (t >= 5 && path[t - 5][hmm::State::Sr] != Some(hmm::State::R));
+    let reverse_stop = t >= 3
+        && seq[t - 1] == A
+        && ((seq[t - 2] == T && seq[t - 3] == T)
+            || (seq[t - 2] == T && seq[t - 3] == C)
+            || (seq[t - 2] == C && seq[t - 3] == T));
+    let (prev, cur) = alpha.pair_mut(t);
+    let path = &mut path[t];
+    let lanes = |first: hmm::State| first as usize..first as usize + hmm::PERIOD;
+
+    // M state
+    let (mut value, mut state) = match_lanes(
+        prev,
+        &MATCH,
+        F64x6::load(e_m),
+        global,
+        indel_d,
+        whole_genome,
+    );
+    let temp_alpha = prev[hmm::State::S] - e_m[0];
+    if temp_alpha <= value.first() {
+        let first = [true, false, false, false, false, false];
+        value = value.select(F64x6::splat(temp_alpha), F64x6::mask(&first));
+        state[0] = hmm::State::S;
+    }
+    let allowed = MATCH.map(|to_m| {
+        let k = to_m as usize - hmm::State::M1 as usize;
+        forward_insertion_allowed(seq, temp_i[(k + 5) % hmm::PERIOD], t, to_m)
+    });
+    let value =
+        insertion_to_match_lanes(value, &mut state, prev, &allowed, global, &INSERTION);
+    let current = F64x6::load(&cur[lanes(hmm::State::M1)]);
+    let finite = current.finite();
+    current
+        .select(value, finite)
+        .store(&mut cur[lanes(hmm::State::M1)]);
+    for (k, &finite) in finite.bits().iter().enumerate() {
+        if finite {
+            path[MATCH[k]] = Some(state[k]);
+        }
+    }
+
+    // I state
+    let m = F64x6::load(&prev[lanes(hmm::State::M1)]);
+    let i = F64x6::load(&prev[lanes(hmm::State::I1)]);
+    let (value, state, entered) =
+        insertion_lanes(i, m, global, indel_i, from, to, true, &INSERTION, &MATCH);
+    value.store(&mut cur[lanes(hmm::State::I1)]);
+    for k in 0..hmm::PERIOD {
+        path[INSERTION[k]] = Some(state[k]);
+        if entered[k] {
+            temp_i[k] = t - 1;
+        }
+    }
+
+    // M' state
+    let emission = F64x6::load(e_m1);
+    let (value, mut state) = match_lanes(
+        prev,
+        &MATCH_REVERSE,
+        emission,
+        global,
+        indel_d,
+        whole_genome,
+    );
+    let allowed = MATCH_REVERSE.map(|to_m| {
+        let k = to_m as usize - hmm::State::M1r as usize;
+        reverse_insertion_allowed(seq, temp_i_1[(k + 5) % hmm::PERIOD], t, to_m)
+    });
+    let mut value = insertion_to_match_lanes(
+        value,
+        &mut state,
+        prev,
+        &allowed,
+        global,
+        &INSERTION_REVERSE,
+    );
+    if reverse_stop {
+        // from Start state since this is actually a stop codon in minus strand
+        let stops = [true, false, false, true, false, false];
+        let temp_alpha = F64x6::splat(prev[hmm::State::Sr]).sub(emission);
+        value = value.select(temp_alpha, F64x6::mask(&stops));
+        for k in [0, 3] {
+            state[k] = hmm::State::Sr;
+        }
+    }
+    value.store(&mut cur[lanes(hmm::State::M1r)]);
+    for k in 0..hmm::PERIOD {
+        path[MATCH_REVERSE[k]] = Some(state[k]);
+    }
+
+    // I' state
+    let m = F64x6::load(&prev[lanes(hmm::State::M1r)]);
+    let i = F64x6::load(&prev[lanes(hmm::State::I1r)]);
+    let (value, state, entered) = insertion_lanes(
+        i,
+        m,
+        global,
+        indel_i,
+        from,
+        to,
+        reverse_insertions,
+        &INSERTION_REVERSE,
+        &MATCH_REVERSE,
+    );
+    value.store(&mut cur[lanes(hmm::State::I1r)]);
+    for k in 0..hmm::PERIOD {
+        path[INSERTION_REVERSE[k]] = Some(state[k]);
+        if entered[k] {
+            temp_i_1[k] = t - 1;
+        }
+    }
+}
+
+/// The match states of a strand from the match states of the previous
+/// column, as `from_m_to_m` followed by `from_d_to_m`.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+#[allow(clippy::needless_range_loop)]
+unsafe fn match_lanes(
+    prev: &[f64; hmm::State::COUNT],
+    states: &[hmm::State; hmm::PERIOD],
+    emission: avx2::F64x6,
+    global: &hmm::Global,
+    indel: &hmm::Indel,
+    whole_genome: bool,
+) -> (avx2::F64x6, [hmm::State; hmm::PERIOD]) {
+    use avx2::F64x6;
+
+    let mut last_m = [0.0; hmm::PERIOD];
+    last_m[0] = global.tr.gg;
+    let mut value = F64x6::new(PREVIOUS.map(|j| prev[states[j]]))
+        .sub(F64x6::new(last_m))
+        .sub(F64x6::splat(global.tr.mm))
+        .sub(emission);
+    let mut from = PREVIOUS;
+    if !whole_genome {
+        let longest = F64x6::splat((global.max_deletion_length + 1) as f64);
+        for d in 0..hmm::PERIOD - 1 {
+            let num_d: [usize; hmm::PERIOD] = std::array::from_fn(|k| DELETIONS[k][d]);
+            let j: [usize; hmm::PERIOD] =
+                std::array::from_fn(|k| (k + hmm::PERIOD - num_d[k] % hmm::PERIOD) % hmm::PERIOD);
+            let num_d = F64x6::new(num_d.map(|n| n as f64));
+            let temp_alpha = F64x6::new(j.map(|j| prev[states[j]]))
+                .sub(F64x6::splat(indel.md))
+                .sub(emission)
+                .sub(F64x6::splat(global.deleted_nucleotide).mul(num_d.sub(F64x6::splat(1.0))))
+                .sub(F64x6::splat(indel.dd).mul(num_d.sub(F64x6::splat(2.0))))
+                .sub(F64x6::splat(global.tr.dm));
+            let better = temp_alpha.lt(value).and(num_d.le(longest));
+            value = value.select(temp_alpha, better);
+            for (k, &better) in better.bits().iter().enumerate() {
+                if better {
+                    from[k] = j[k];
+                }
+            }
+        }
+    }
+    (value, from.map(|j| states[j]))
+}
+
+/// Leaves the insertion states of the previous column for the match states
+/// where `allowed`, as `from_i_to_m` or `from_i1_to_m1`.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn insertion_to_match_lanes(
+    value: avx2::F64x6,
+    state: &mut [hmm::State; hmm::PERIOD],
+    prev: &[f64; hmm::State::COUNT],
+    allowed: &[bool; hmm::PERIOD],
+    global: &hmm::Global,
+    states: &[hmm::State; hmm::PERIOD],
+) -> avx2::F64x6 {
+    use avx2::F64x6;
+
+    let temp_alpha = F64x6::new(PREVIOUS.map(|j| prev[states[j]]))
+        .sub(F64x6::splat(global.tr.im))
+        .sub(F64x6::splat(0.25_f64.ln()));
+    let better = temp_alpha.lt(value).and(F64x6::mask(allowed));
+    for (k, &better) in better.bits().iter().enumerate() {
+        if better {
+            state[k] = states[PREVIOUS[k]];
+        }
+    }
+    value.select(temp_alpha, better)
+}
+
+/// The insertion states of a strand, as `from_i_to_i` followed by
+/// `from_m_to_i` if `from_match`, with the lanes entered from a match state.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+#[allow(clippy::too_many_arguments)]
+unsafe fn insertion_lanes(
+    i: avx2::F64x6,
+    m: avx2::F64x6,
+    global: &hmm::Global,
+    indel: &hmm::Indel,
+    from: usize,
+    to: usize,
+    from_match: bool,
+    states: &[hmm::State; hmm::PERIOD],
+    match_states: &[hmm::State; hmm::PERIOD],
+) -> (avx2::F64x6, [hmm::State; hmm::PERIOD], [bool; hmm::PERIOD]) {
+    use avx2::F64x6;
+
+    let mut value = i
+        .sub(F64x6::splat(indel.ii))
+        .sub(F64x6::splat(global.tr_ii[from][to]));
+    let mut state = *states;
+    let mut entered = [false; hmm::PERIOD];
+    if from_match {
+        let mut last_i = [0.0; hmm::PERIOD];
+        last_i[hmm::PERIOD - 1] = global.tr.gg;
+        let temp_alpha = m
+            .sub(F64x6::splat(indel.mi))
+            .sub(F64x6::splat(global.tr_mi[from][to]))
+            .sub(F64x6::new(last_i));
+        let better = temp_alpha.lt(value);
+        value = value.select(temp_alpha, better);
+        entered = better.bits();
+        for k in 0..hmm::PERIOD {
+            if entered[k] {
+                state[k] = match_states[k];
+            }
+        }
+    }
+    (value, state, entered)
+}
+
+#[allow(clippy::manual_clamp, clippy::neg_multiply)]
 fn modify_border_dist(cell: &mut f64, values: &[f64], start_freq: f64) {
     let h_kd =
         values[2] * (-1.0 * (start_freq - values[1]).powi(2) / (values[0]).powi(2) / 2.0).exp();
@@ -1034,15 +2020,31 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    use proptest::prelude::*;
+
     use crate::dna::dna;
 
     fn test_viterbi(file: &str, seq: Vec<Nuc>, genes: Vec<gene::Gene>) {
         let (global, locals) =
             hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from(file)).unwrap();
-        let received = viterbi(&global, &locals, vec![], seq, false);
+        let cg = count_cg_content(&seq);
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq,
+            &[],
+            false,
+            &gene::GeneFilter::new(false),
+            Trellis::Full,
+        )
+        .unwrap();
         let expected = gene::ReadPrediction {
             head: vec![],
-            genes: genes,
+            description: vec![],
+            genes,
+            none_states: 0,
+            cg: Some(cg),
         };
         assert_eq!(expected, received);
         // assert_eq!(expected, received)
@@ -1089,6 +2091,393 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_filter() {
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("454_10")).unwrap();
+        let seq = dna("GTCGACAGTGTAGTAACCAGTGCTCACGATACCATTGTGGGATCAGCGACCAGAGTTGCTGCAACATTTCACCGCTGGTAACAACGACCATCG");
+        let mut filter = gene::GeneFilter::new(false);
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert_eq!(1, received.genes.len());
+
+        filter.min_length = 100;
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert!(received.genes.is_empty());
+
+        filter = gene::GeneFilter::new(false);
+        filter.min_protein_length = 31;
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert!(received.genes.is_empty());
+
+        filter = gene::GeneFilter::new(false);
+        filter.min_score = 1.4;
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert!(received.genes.is_empty());
+
+        filter = gene::GeneFilter::new(false);
+        filter.max_indels = 0;
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq,
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert!(received.genes.is_empty());
+    }
+
+    #[test]
+    fn test_ambiguity_and_mask() {
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("454_10")).unwrap();
+        let filter = gene::GeneFilter::new(false);
+        let seq = dna("GTCGACAGTGTAGTAACCAGTGCTCACGATACCATTGTGGGATCAGCGACCAGAGTTGCTGCAACATTTCACCGCTGGTAACAACGACCATCG");
+        let expected = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+
+        // ambiguity codes compatible with the original nucleotides
+        let mut ambiguous = seq.clone();
+        ambiguous[40] = Nuc::R;
+        ambiguous[41] = Nuc::Y;
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            ambiguous,
+            &[],
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert_eq!(1, received.genes.len());
+        assert_eq!(expected.genes[0].start, received.genes[0].start);
+        assert_eq!(expected.genes[0].end, received.genes[0].end);
+        assert!(received.genes[0].dna.contains(&Nuc::R));
+
+        let mask = vec![true; seq.len()];
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &mask,
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert!(received.genes.is_empty());
+
+        let mask = vec![false; seq.len()];
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq,
+            &mask,
+            false,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert_eq!(expected, received);
+    }
+
+    #[test]
+    fn test_unusable_sequences() {
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("454_10")).unwrap();
+        let filter = gene::GeneFilter::new(false);
+        for (seq, error) in [
+            ("", ReadError::TooShort(0)),
+            ("AC", ReadError::TooShort(2)),
+            ("NNNNNNNNNN", ReadError::NoNucleotides),
+        ] {
+            let received = viterbi(
+                &global,
+                &locals,
+                vec![],
+                dna(seq),
+                &[],
+                false,
+                &filter,
+                Trellis::Full,
+            );
+            assert_eq!(Err(&error), received.as_ref());
+            let received = forward::<
+                Vec<[f64; hmm::State::COUNT]>,
+                Vec<[Option<hmm::State>; hmm::State::COUNT]>,
+            >(&global, &locals[0], &dna(seq), &[], false);
+            assert_eq!(Err(error), received.map(|_| ()));
+        }
+        for seq in [
+            "ACG",
+            "TAA",
+            "TTA",
+            "NNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNNAAATG",
+        ] {
+            for whole_genome in [false, true] {
+                let received = viterbi(
+                    &global,
+                    &locals,
+                    vec![],
+                    dna(seq),
+                    &[],
+                    whole_genome,
+                    &filter,
+                    Trellis::Full,
+                );
+                assert!(received.unwrap().genes.is_empty());
+            }
+            let received = viterbi_long_read(
+                &global,
+                &locals,
+                vec![],
+                dna(seq),
+                &[],
+                &filter,
+                Trellis::Full,
+            );
+            assert!(received.unwrap().genes.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_long_read_windows() {
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("tests/data"), PathBuf::from("homopolymer"))
+                .unwrap();
+        let unit = dna("TGTTCGCTGGCGGTGCTTTAGGGGGAGATGCGCAGAATGTCTCAGCCCTGCAAGGGGGTACTCTAGACATGGTGGTATTGAATTCCGGTATCTTAGCTTCGCAAGTGAAAGAGTTTGCCGTGTATGACTTTCCGTTCATGTTCAATAACTCTGAAGAAGCCGATAAAGTCGTCGACGGTGAATTTGGTAAGGCTTTGCACGCGAAATTAGAAGAGAAAGGTATTATCGGTTTAGCCTATTGGGAGTTGGGCTTCCGCGATATGACTAATAGCAAACACCCTATCACTAAGGTCGAGGATATTAAAGGTCTGAAACTGCGTGTTATCCCTAATTAGTTAATAGCTAACTTATAATTTAGATTATCTAA");
+        let seq: Vec<Nuc> = unit.iter().cycle().take(unit.len() * 12).copied().collect();
+        let filter = gene::GeneFilter::new(true);
+
+        let whole = viterbi_windows(
+            &global,
+            &locals,
+            vec![],
+            seq.clone(),
+            &[],
+            &filter,
+            Trellis::Full,
+            seq.len(),
+            0,
+        );
+        let windowed = viterbi_windows(
+            &global,
+            &locals,
+            vec![],
+            seq,
+            &[],
+            &filter,
+            Trellis::Full,
+            1500,
+            700,
+        );
+        // scores differ in the last bits, as windows accumulate from another origin
+        let structure = |prediction: gene::ReadPrediction| -> Vec<_> {
+            prediction
+                .genes
+                .into_iter()
+                .map(|g| (g.start, g.end, g.frame, g.forward_strand, g.dna))
+                .collect()
+        };
+        assert_eq!(12, whole.genes.len());
+        assert_eq!(structure(whole), structure(windowed));
+    }
+
+    #[test]
+    fn test_long_genes_at_window_ends() {
+        // genes of 335 nucleotides, longer than half the overlap, some of
+        // which start just before the end of the center region of a window
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("tests/data"), PathBuf::from("homopolymer"))
+                .unwrap();
+        let unit = dna("TGTTCGCTGGCGGTGCTTTAGGGGGAGATGCGCAGAATGTCTCAGCCCTGCAAGGGGGTACTCTAGACATGGTGGTATTGAATTCCGGTATCTTAGCTTCGCAAGTGAAAGAGTTTGCCGTGTATGACTTTCCGTTCATGTTCAATAACTCTGAAGAAGCCGATAAAGTCGTCGACGGTGAATTTGGTAAGGCTTTGCACGCGAAATTAGAAGAGAAAGGTATTATCGGTTTAGCCTATTGGGAGTTGGGCTTCCGCGATATGACTAATAGCAAACACCCTATCACTAAGGTCGAGGATATTAAAGGTCTGAAACTGCGTGTTATCCCTAATTAGTTAATAGCTAACTTATAATTTAGATTATCTAA");
+        let forward: Vec<Nuc> = unit.iter().cycle().take(unit.len() * 12).copied().collect();
+        let reverse: Vec<Nuc> = forward.iter().rev().map(Nuc::rc).collect();
+        let filter = gene::GeneFilter::new(true);
+        let genes = |seq: &Vec<Nuc>, window_length: usize, overlap: usize| -> Vec<_> {
+            viterbi_windows(
+                &global,
+                &locals,
+                vec![],
+                seq.clone(),
+                &[],
+                &filter,
+                Trellis::Full,
+                window_length,
+                overlap,
+            )
+            .genes
+            .into_iter()
+            .map(|g| (g.start, g.end, g.forward_strand))
+            .collect()
+        };
+        for seq in [forward, reverse] {
+            let whole = genes(&seq, seq.len(), 0);
+            assert_eq!(12, whole.len());
+            for (window_length, overlap) in [(1000, 400), (1100, 400), (800, 300)] {
+                assert_eq!(whole, genes(&seq, window_length, overlap));
+            }
+        }
+    }
+
+    #[test]
+    fn test_viterbi_reusing() {
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("illumina_5")).unwrap();
+        let seq = "TGTTCGCTGGCGGTGCTTTAGGGGGAGATGCGCAGAATGTCTCAGCCCTGCAAGGGGGTACTCTAGACATGGTGGTATTGAATTCCGGTATCTTAGCTTCGCAAGTGAAAGAGTTTGCCGTGTATGACTTTCCGTTCATGTTCAATAACTCTGAAGAAGCCGATAAAGTCGTCGACGGTGAATTTGGTAAGGCTTTGCACGCGAAATTAGAAGAGAAAGGTATTATCGGTTTAGCCTATTGGGAGTTGGGCTTCCGCGATATGACTAATAGCAAACACCCTATCACTAAGGTCGAGGATATTAAAGGTCTGAAACTGCGTGTTATCCC";
+        let reads = || {
+            [
+                (0, 150),
+                (40, 190),
+                (100, 102),
+                (7, 157),
+                (3, 303),
+                (150, 300),
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, &(start, end))| {
+                let mask = if i == 3 {
+                    (start..end).map(|p| p % 50 < 10).collect()
+                } else {
+                    vec![]
+                };
+                (vec![i as u8], dna(&seq[start..end]), mask)
+            })
+            .collect::<Vec<_>>()
+        };
+        let filter = gene::GeneFilter::new(false);
+
+        let mut scratch = Scratch::default();
+        for trellis in [Trellis::Full, Trellis::Compact] {
+            let expected: Vec<_> = reads()
+                .into_iter()
+                .map(|(head, seq, mask)| {
+                    viterbi(&global, &locals, head, seq, &mask, false, &filter, trellis)
+                })
+                .collect();
+            assert_eq!(Err(ReadError::TooShort(2)), expected[2]);
+            for _ in 0..2 {
+                let received = viterbi_reusing(
+                    &mut scratch,
+                    &global,
+                    &locals,
+                    reads(),
+                    false,
+                    &filter,
+                    trellis,
+                );
+                assert_eq!(expected, received);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_trellis() {
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("complete")).unwrap();
+        let unit = dna("TGTTCGCTGGCGGTGCTTTAGGGGGAGATGCGCAGAATGTCTCAGCCCTGCAAGGGGGTACTCTAGACATGGTGGTATTGAATTCCGGTATCTTAGCTTCGCAAGTGAAAGAGTTTGCCGTGTATGACTTTCCGTTCATGTTCAATAACTCTGAAGAAGCCGATAAAGTCGTCGACGGTGAATTTGGTAAGGCTTTGCACGCGAAATTAGAAGAGAAAGGTATTATCGGTTTAGCCTATTGGGAGTTGGGCTTCCGCGATATGACTAATAGCAAACACCCTATCACTAAGGTCGAGGATATTAAAGGTCTGAAACTGCGTGTTATCCCTAATTAGTTAATAGCTAACTTATAATTTAGATTATCTAA");
+        let seq: Vec<Nuc> = unit.iter().cycle().take(unit.len() * 12).copied().collect();
+        let filter = gene::GeneFilter::new(true);
+        let decode = |trellis| {
+            viterbi(
+                &global,
+                &locals,
+                vec![],
+                seq.clone(),
+                &[],
+                true,
+                &filter,
+                trellis,
+            )
+            .unwrap()
+        };
+
+        let full = decode(Trellis::Full);
+        let compact = decode(Trellis::Compact);
+        assert_eq!(12, full.genes.len());
+        assert_eq!(full.genes.len(), compact.genes.len());
+        assert_eq!(full.none_states, compact.none_states);
+        for (expected, received) in full.genes.iter().zip(&compact.genes) {
+            let structure = |g: &gene::Gene| (g.start, g.end, g.frame, g.forward_strand);
+            assert_eq!(structure(expected), structure(received));
+            assert_eq!(expected.dna, received.dna);
+            assert!((expected.score - received.score).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_homopolymer_transitions() {
+        let (global, _) =
+            hmm::get_train_from_file(PathBuf::from("tests/data"), PathBuf::from("homopolymer"))
+                .unwrap();
+        assert_eq!(global.indel(1).mi, global.tr.mi);
+        assert_eq!(global.indel(1).dd, global.tr.dd);
+        assert_eq!(global.indel(3).mi, 0.01_f64.ln());
+        assert_eq!(global.indel(12).md, 0.015_f64.ln());
+
+        let (global, _) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("454_10")).unwrap();
+        assert!(global.tr_homopolymer.is_empty());
+        assert_eq!(global.indel(12).mi, global.tr.mi);
+    }
+
     #[test]
     fn test_deletion() {
         test_viterbi(
@@ -1108,4 +2497,228 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_max_deletion_length() {
+        let (mut global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("454_30")).unwrap();
+        // a gene with a nucleotide (A at 103) deleted
+        let seq = dna("TGTTCGCTGGCGGTGCTTTAGGGGGAGATGCGCAGAATGTCTCAGCCCTGCAAGGGGGTACTCTAGACATGGTGGTATTGAATTCCGGTATCTTAGCTTCGCAGTGAAAGAGTTTGCCGTGTATGACTTTCCGTTCATGTTCAATAACTCTGAAGAAGCCGATAAAGTCGTCGACGGTGAATTTGGTAAGGCTTTGCACG");
+        let deletions = |global: &hmm::Global| -> usize {
+            viterbi(
+                global,
+                &locals,
+                vec![],
+                seq.clone(),
+                &[],
+                false,
+                &gene::GeneFilter::new(false),
+                Trellis::Full,
+            )
+            .unwrap()
+            .genes
+            .iter()
+            .map(|gene| gene.deleted.len())
+            .sum()
+        };
+        assert!(deletions(&global) > 0);
+        global.max_deletion_length = 0;
+        assert_eq!(deletions(&global), 0);
+
+        // unlikely deleted nucleotides rule out the deletion as well
+        let model = crate::config::ModelConfig {
+            deleted_nucleotide: Some(1e-6),
+            ..Default::default()
+        };
+        global.apply_overrides(&model).unwrap();
+        assert_eq!(deletions(&global), 0);
+    }
+
+    #[test]
+    fn test_short_genes_at_ends() {
+        // genes shorter than the window around the start codon, near either
+        // end of the sequence, are reported without refining their start
+        let mut filter = gene::GeneFilter::new(true);
+        filter.min_length = 1;
+        let structure = |prediction: gene::ReadPrediction| -> Vec<_> {
+            prediction
+                .genes
+                .into_iter()
+                .map(|g| (g.start, g.end, g.forward_strand))
+                .collect()
+        };
+
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("train"), PathBuf::from("complete")).unwrap();
+        let seq = dna("CTACTGTATGTCCTTCAACTAAGTTCCTCAAATGTATCTCGAACCATCGCTAAATACCTGGCCGAGTGGATACCTCAATAGAAATACTGCAACGAGTGCCTTGCAGATATAACGTAAAGGTCCGCAGACTAGTATTCCAAAAGATTCCATATCTAGGTAAGCGCCCATGATAGTGCGATGACCCAAGCATCTTATTTTCCAGTGGACACACAGCACAACCACATACTACATAATCGATAGTGGAGGGATGGAAATGCTTGGGGCACA");
+        let received = viterbi(
+            &global,
+            &locals,
+            vec![],
+            seq,
+            &[],
+            true,
+            &filter,
+            Trellis::Full,
+        )
+        .unwrap();
+        assert_eq!(vec![(1, 18, false), (248, 265, true)], structure(received));
+
+        let (global, locals) =
+            hmm::get_train_from_file(PathBuf::from("tests/data"), PathBuf::from("homopolymer"))
+                .unwrap();
+        let seq = dna("TTAATTGTAGGCGGTTACCGGCGTCTGCCCAGTACAATCCCAGGCCTTAGCATACTAGCACCTTCGCAGTACTCTCCCATCTCTATGGCTAAGACTCTCAAGCTAATTCTCTAGGTAGCCTGGGGGCATGACCAAAGGTCAAGGACTACAATGGGT");
+        let received =
+            viterbi_long_read(&global, &locals, vec![], seq, &[], &filter, Trellis::Full).unwrap();
+        assert_eq!(vec![(1, 101, false), (128, 154, true)], structure(received));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_lanes() {
+        if !lanes_supported() {
+            return;
+        }
+        // xorshift, to cover many sequences without a dependency on rand
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        let nucleotides = b"ACGTACGTACGTACGTACGTACGTACGTACGTNRY";
+        // the trellises are reused, as by `viterbi_reusing`
+        let (mut alpha, mut path) = (vec![], vec![]);
+        let (mut lanes_alpha, mut lanes_path) = (vec![], vec![]);
+        for (dir, file) in [
+            ("train", "454_10"),
+            ("train", "illumina_1"),
+            ("train", "complete"),
+            ("tests/data", "homopolymer"),
+        ] {
+            let (global, locals) =
+                hmm::get_train_from_file(PathBuf::from(dir), PathBuf::from(file)).unwrap();
+            for _ in 0..25 {
+                let length = 3 + random(600) as usize;
+                let seq: Vec<Nuc> = (0..length)
+                    .map(|_| Nuc::from(nucleotides[random(nucleotides.len() as u64) as usize]))
+                    .collect();
+                let mask: Vec<bool> = if random(4) == 0 {
+                    (0..length).map(|_| random(20) == 0).collect()
+                } else {
+                    vec![]
+                };
+                let local = &locals[random(locals.len() as u64) as usize];
+                for whole_genome in [false, true] {
+                    forward_kernel::<_, _, false>(
+                        &mut alpha,
+                        &mut path,
+                        &global,
+                        local,
+                        &seq,
+                        &mask,
+                        whole_genome,
+                    );
+                    // SAFETY: the processor supports AVX2.
+                    unsafe {
+                        forward_avx2(
+                            &mut lanes_alpha,
+                            &mut lanes_path,
+                            &global,
+                            local,
+                            &seq,
+                            &mask,
+                            whole_genome,
+                        )
+                    };
+                    assert_eq!(path, lanes_path);
+                    for (column, lanes_column) in alpha.iter().zip(&lanes_alpha) {
+                        assert_eq!(column.map(f64::to_bits), lanes_column.map(f64::to_bits));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The short read, homopolymer and complete models, loaded once.
+    fn models() -> &'static [(Box<hmm::Global>, Vec<hmm::Local>)] {
+        static MODELS: std::sync::OnceLock<Vec<(Box<hmm::Global>, Vec<hmm::Local>)>> =
+            std::sync::OnceLock::new();
+        MODELS.get_or_init(|| {
+            [
+                ("train", "454_10"),
+                ("tests/data", "homopolymer"),
+                ("train", "complete"),
+            ]
+            .iter()
+            .map(|(dir, file)| {
+                hmm::get_train_from_file(PathBuf::from(dir), PathBuf::from(file)).unwrap()
+            })
+            .collect()
+        })
+    }
+
+    /// Bytes that are mostly nucleotides, in upper or lower case.
+    fn sequence() -> impl Strategy<Value = Vec<u8>> {
+        let byte = prop_oneof![
+            8 => prop::sample::select(b"ACGTacgt".to_vec()),
+            1 => any::<u8>(),
+        ];
+        prop::collection::vec(byte, 0..400)
+    }
+
+    fn check_prediction(prediction: &gene::ReadPrediction, length: usize, complete: bool) {
+        let mut buf = vec![];
+        let ids = gene::GeneIds::default();
+        prediction.meta(&mut buf, &ids).unwrap();
+        prediction.gff(&mut buf, &ids).unwrap();
+        prediction.dna(&mut buf, true, &ids).unwrap();
+        prediction.protein(&mut buf, complete, &ids).unwrap();
+        for gene in &prediction.genes {
+            assert!(1 <= gene.start && gene.start <= gene.end && gene.end <= length);
+            assert!((1..=3).contains(&gene.frame));
+            for &position in gene.inserted.iter().chain(&gene.deleted) {
+                assert!(gene.start <= position && position <= gene.end);
+            }
+            gene.translate(complete);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_arbitrary_sequence(
+            bytes in sequence(),
+            mask in prop::collection::vec(any::<bool>(), 0..400),
+            model in 0..3usize,
+            compact in any::<bool>(),
+            min_length in prop::option::of(1..130usize),
+        ) {
+            let (global, locals) = &models()[model];
+            let seq: Vec<Nuc> = bytes.iter().map(|&b| Nuc::from(b)).collect();
+            let mask = &mask[..mask.len().min(seq.len())];
+            let trellis = if compact { Trellis::Compact } else { Trellis::Full };
+            let filter = |whole_genome| {
+                let mut filter = gene::GeneFilter::new(whole_genome);
+                filter.min_length = min_length.unwrap_or(filter.min_length);
+                filter
+            };
+            for whole_genome in [false, true] {
+                match viterbi(global, locals, b"read".to_vec(), seq.clone(), mask, whole_genome, &filter(whole_genome), trellis) {
+                    Ok(prediction) => check_prediction(&prediction, seq.len(), whole_genome),
+                    Err(e) => prop_assert_eq!(Err(e), check(&seq)),
+                }
+            }
+            match viterbi_long_read(global, locals, b"read".to_vec(), seq.clone(), mask, &filter(true), trellis) {
+                Ok(prediction) => check_prediction(&prediction, seq.len(), true),
+                Err(e) => prop_assert_eq!(Err(e), check(&seq)),
+            }
+            if check(&seq).is_ok() {
+                let prediction = viterbi_windows(global, locals, vec![], seq.clone(), mask, &filter(true), trellis, 150, 60);
+                check_prediction(&prediction, seq.len(), true);
+            }
+        }
+    }
 }
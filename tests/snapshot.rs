@@ -0,0 +1,190 @@
+//! Snapshot tests: runs the binary on the sample inputs in `tests/data` and
+//! compares the output files byte for byte with earlier outputs of this crate
+//! in `tests/data/snapshots`, so any change in behaviour shows up as a
+//! failure.
+//!
+//! The snapshots were written by FragGeneScanRs 1.1.0 itself, not by FGS or
+//! FGS+, so they catch regressions but don't check agreement with the
+//! original tools. After an intended change in the output, rewrite them with
+//! `SNAPSHOT_UPDATE=1 cargo test --test snapshot` and review the diff. The
+//! provenance comments are not compared.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const EXTENSIONS: [&str; 4] = ["out", "gff", "faa", "ffn"];
+
+fn data() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+}
+
+/// Removes the provenance comments of the `.gff` and `.out` files, which
+/// differ between runs, after checking that they name the training file.
+fn without_provenance(output: &[u8], extension: &str, train: &str) -> Vec<u8> {
+    let (provenance, rest): (Vec<&[u8]>, Vec<&[u8]>) = output
+        .split_inclusive(|&c| c == b'\n')
+        .partition(|line| line.starts_with(b"#!") || line.starts_with(b"# "));
+    if extension == "gff" || extension == "out" {
+        let line = format!("training-file {}\n", train);
+        assert!(
+            provenance.iter().any(|l| l.ends_with(line.as_bytes())),
+            "no provenance in .{}",
+            extension
+        );
+    }
+    rest.concat()
+}
+
+/// Runs the binary on `input` with the training file and `--complete`, and
+/// compares the outputs with the snapshots named `name`.
+fn snapshot(name: &str, input: &str, train: &str, complete: bool) {
+    let output = tempfile::tempdir().unwrap();
+    let prefix = output.path().join(name);
+    let status = Command::new(env!("CARGO_BIN_EXE_FragGeneScanRs"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("-s")
+        .arg(data().join(input))
+        .arg("-o")
+        .arg(&prefix)
+        .args(["-w", if complete { "1" } else { "0" }, "-t", train])
+        .status()
+        .unwrap();
+    assert!(status.success(), "{}: {}", name, status);
+
+    let update = env::var_os("SNAPSHOT_UPDATE").is_some();
+    let mut different = vec![];
+    for extension in EXTENSIONS {
+        let received = without_provenance(
+            &fs::read(prefix.with_extension(extension)).unwrap(),
+            extension,
+            train,
+        );
+        let path = data()
+            .join("snapshots")
+            .join(format!("{}.{}", name, extension));
+        if update {
+            fs::write(&path, &received).unwrap();
+            continue;
+        }
+        let expected = fs::read(&path).unwrap();
+        if expected != received {
+            let line = expected
+                .split(|&c| c == b'\n')
+                .zip(received.split(|&c| c == b'\n'))
+                .position(|(e, r)| e != r)
+                .map_or("end".to_string(), |l| (l + 1).to_string());
+            different.push(format!("{}.{} (from line {})", name, extension, line));
+        }
+    }
+    for extension in ["faa", "ffn"] {
+        let sidecar = fs::read(prefix.with_extension(format!("{}.json", extension))).unwrap();
+        let provenance: serde_json::Value = serde_json::from_slice(&sidecar).unwrap();
+        assert_eq!(provenance["training_file"], train, "{}.{}", name, extension);
+        assert_eq!(
+            provenance["whole_genome"], complete,
+            "{}.{}",
+            name, extension
+        );
+    }
+    assert!(
+        different.is_empty(),
+        "output differs: {}",
+        different.join(", ")
+    );
+}
+
+#[test]
+fn test_snapshot_complete() {
+    snapshot("reads_complete", "reads.fna", "complete", false);
+}
+
+#[test]
+fn test_snapshot_sanger_5() {
+    snapshot("reads_sanger_5", "reads.fna", "sanger_5", false);
+}
+
+#[test]
+fn test_snapshot_sanger_10() {
+    snapshot("reads_sanger_10", "reads.fna", "sanger_10", false);
+}
+
+#[test]
+fn test_snapshot_454_5() {
+    snapshot("reads_454_5", "reads.fna", "454_5", false);
+}
+
+#[test]
+fn test_snapshot_454_10() {
+    snapshot("reads_454_10", "reads.fna", "454_10", false);
+}
+
+#[test]
+fn test_snapshot_454_30() {
+    snapshot("reads_454_30", "reads.fna", "454_30", false);
+}
+
+#[test]
+fn test_snapshot_illumina_1() {
+    snapshot("reads_illumina_1", "reads.fna", "illumina_1", false);
+}
+
+#[test]
+fn test_snapshot_illumina_5() {
+    snapshot("reads_illumina_5", "reads.fna", "illumina_5", false);
+}
+
+#[test]
+fn test_snapshot_illumina_10() {
+    snapshot("reads_illumina_10", "reads.fna", "illumina_10", false);
+}
+
+#[test]
+fn test_snapshot_whole_genome() {
+    snapshot("genome_complete", "genome.fna", "complete", true);
+}
+
+/// Evaluates the predictions on the genome, read from the freshly written
+/// `.out` and `.gff` files, against the `.gff` file itself.
+#[test]
+fn test_evaluate_output() {
+    let output = tempfile::tempdir().unwrap();
+    let prefix = output.path().join("genome");
+    let status = Command::new(env!("CARGO_BIN_EXE_FragGeneScanRs"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("-s")
+        .arg(data().join("genome.fna"))
+        .arg("-o")
+        .arg(&prefix)
+        .args(["-w", "1", "-t", "complete"])
+        .status()
+        .unwrap();
+    assert!(status.success(), "{}", status);
+
+    let evaluate = |extension: &str| -> serde_json::Value {
+        let evaluation = Command::new(env!("CARGO_BIN_EXE_FragGeneScanRs"))
+            .arg("evaluate")
+            .arg("-p")
+            .arg(prefix.with_extension(extension))
+            .arg("-g")
+            .arg(prefix.with_extension("gff"))
+            .arg("--json")
+            .output()
+            .unwrap();
+        assert!(
+            evaluation.status.success(),
+            "{}",
+            String::from_utf8_lossy(&evaluation.stderr)
+        );
+        serde_json::from_slice(&evaluation.stdout).unwrap()
+    };
+    let evaluation = evaluate("out");
+    assert_eq!(evaluation, evaluate("gff"));
+    let genes = &evaluation["genes"];
+    assert!(genes["predicted"].as_u64().unwrap() > 0);
+    assert_eq!(genes["predicted"], genes["annotated"]);
+    assert_eq!(genes["predicted"], genes["correct_starts"]);
+}